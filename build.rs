@@ -0,0 +1,95 @@
+//! Records how the binary was built, for the environment file of each run
+//! (see `src/environment.rs`).
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(cmd).args(args).output().ok()?;
+    if out.status.success() {
+        Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// The revision egg was resolved to, from its `source` line in Cargo.lock,
+/// like `git+https://github.com/egraphs-good/egg?rev=...#<commit>`.
+fn egg_rev(lock: &str) -> Option<String> {
+    let source = lock
+        .lines()
+        .skip_while(|l| *l != "name = \"egg\"")
+        .take_while(|l| !l.is_empty())
+        .find(|l| l.starts_with("source = "))?;
+    match source.rsplit_once('#') {
+        Some((_, rev)) => Some(rev.trim_end_matches('"').to_string()),
+        None => Some(source.trim_start_matches("source = ").replace('"', "")),
+    }
+}
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let lock = fs::read_to_string(format!("{}/Cargo.lock", manifest_dir)).unwrap_or_default();
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+
+    let commit = output("git", &["rev-parse", "HEAD"]).map(|commit| {
+        let clean = Command::new("git")
+            .args(["diff-index", "--quiet", "HEAD", "--"])
+            .status()
+            .map_or(true, |s| s.success());
+        if clean {
+            commit
+        } else {
+            commit + "-dirty"
+        }
+    });
+
+    let unknown = || "unknown".to_string();
+    let vars = [
+        ("EGG_BENCH_RUSTC", output(&rustc, &["--version"])),
+        ("EGG_BENCH_EGG_REV", egg_rev(&lock)),
+        ("EGG_BENCH_PROFILE", env::var("PROFILE").ok()),
+        ("EGG_BENCH_COMMIT", commit),
+    ];
+    for (name, value) in vars.iter() {
+        let value = value.clone().unwrap_or_else(unknown);
+        println!("cargo:rustc-env={}={}", name, value);
+    }
+    // Listing any file turns off the default rerun on every change in the
+    // package, so the sources are listed too, for the dirty flag.
+    let sources = [
+        "Cargo.lock",
+        "Cargo.toml",
+        "build.rs",
+        "src",
+        "patterns.csv",
+    ];
+    for path in sources.iter() {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    for path in git_files() {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+}
+
+/// The files of the repository that change with the commit: HEAD for a
+/// switch of branches, the ref of the branch for a new commit on it, which
+/// is in packed-refs once git packs it, and the index for the dirty flag.
+/// Cargo reruns the script every time if one of them doesn't exist, so only
+/// those that do are listed.
+fn git_files() -> Vec<String> {
+    let git_dir = output("git", &["rev-parse", "--git-dir"]).unwrap_or_else(|| ".git".into());
+    let mut files = vec!["HEAD".to_string(), "index".into(), "packed-refs".into()];
+    // refs/heads/<branch>, or HEAD itself when it's detached
+    if let Some(head) = output("git", &["rev-parse", "--symbolic-full-name", "HEAD"]) {
+        if head.starts_with("refs/") {
+            files.push(head);
+        }
+    }
+    files
+        .into_iter()
+        .map(|file| format!("{}/{}", git_dir, file))
+        .filter(|path| fs::metadata(path).is_ok())
+        .collect()
+}
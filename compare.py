@@ -4,6 +4,7 @@ import re
 import argparse
 import csv
 from collections import Counter
+from itertools import product
 from statistics import median, harmonic_mean, geometric_mean
 
 parser = argparse.ArgumentParser(description='Process e-matching benchmarking data')
@@ -13,6 +14,8 @@ parser.add_argument('--plot', help='Write the plot to this file')
 parser.add_argument('--show-plot', action='store_true', help='Make and show the plot')
 parser.add_argument('--timeout', type=float, 
                     help='How long to assume timeouts took in seconds (default is the reported time)')
+parser.add_argument('--by-category', action='store_true',
+                    help='Split each row by pattern type (linear, nonlinear, ground)')
 args = parser.parse_args()
 
 if args.plot or args.show_plot:
@@ -24,14 +27,33 @@ if args.plot or args.show_plot:
     matplotlib.rcParams['ps.fonttype'] = 42
 
 patterns = {}
-for row in csv.DictReader(open('patterns.csv'), skipinitialspace=True):
+# like the benchmark, treat everything after the third comma as the pattern
+pattern_lines = [l for l in open('patterns.csv') if l.strip() and not l.lstrip().startswith('#')]
+pattern_columns = [f.strip() for f in pattern_lines[0].split(',')]
+for line in pattern_lines[1:]:
+    row = dict(zip(pattern_columns, (f.strip() for f in line.split(',', 3))))
     # duplicate keys should be ok here, since they have equal values
-    patterns[row['pattern']] = row
+    # the benchmark prints patterns with single spaces
+    patterns[' '.join(row['pattern'].split())] = row
 
 benches = {}
+failed = []
 reader = csv.DictReader(args.file)
 for row in list(reader):
-    b = benches.setdefault(row['benchmark'], {})
+    # newer results have a status and nanoseconds instead of a signed time
+    if 'status' in row:
+        if row['status'] not in ['ok', 'timeout']:
+            # panics, out of memory and skips say nothing about speed
+            failed.append(row)
+            continue
+        t = int(row['time_ns']) // 1000
+        row['time'] = -t if row['status'] == 'timeout' else t
+
+    # rule searchers from --conditional runs are summarized separately
+    bench = row['benchmark']
+    if row.get('mode', 'pattern') != 'pattern':
+        bench += '/' + row['mode']
+    b = benches.setdefault(bench, {})
     n = b.setdefault(int(row['node_size']), {})
     p = n.setdefault(row['pattern'], {})
     a = p.setdefault(row['algo'], {})
@@ -52,9 +74,19 @@ for row in list(reader):
         row['time'] = t
     assert row['time'] > 0
 
+    # older results don't have the column, so look it up by pattern
+    if not row.get('category'):
+        row['category'] = patterns[' '.join(row['pattern'].split())]['type']
+
     rpt = int(row['repeat_time'])
     a.setdefault(rpt, []).append(row)
 
+if failed:
+    print(f'{len(failed)} searches failed and are left out:')
+    for row in failed:
+        print(f"  {row['benchmark']} {row['node_size']} {row['algo']} {row['repeat_time']}: "
+              f"{row['status']} {row['pattern']} {row.get('message', '')}")
+
 def get_time(row):
     return row['time']
 
@@ -83,7 +115,12 @@ def fmt_x(ratio):
         # return '1/{:.0f}×'.format(1/ratio)
 
 
-print('index,  bench,       size,  gj,  em, TO,   total,    hmean,    gmean,     best,     medn,    worst')
+CATEGORIES = ['linear', 'nonlinear', 'ground']
+
+if args.by_category:
+    print('index,  bench,       size,      type,  gj,  em, TO,   total,    hmean,    gmean,     best,     medn,    worst')
+else:
+    print('index,  bench,       size,  gj,  em, TO,   total,    hmean,    gmean,     best,     medn,    worst')
 
 for bench, sizes in sorted(benches.items()):
     biggest_size = max(sizes.keys())
@@ -91,7 +128,7 @@ for bench, sizes in sorted(benches.items()):
         if not (args.all_egraphs or size == biggest_size):
             continue
 
-        for exclude_gj_index in [0, 1]:
+        for category, exclude_gj_index in product(CATEGORIES if args.by_category else [None], [0, 1]):
 
             em_faster = 0
             em_times = []
@@ -102,8 +139,10 @@ for bench, sizes in sorted(benches.items()):
             gj_times_no_timeout = []
 
             for pat, algos in pats.items():
-                # if patterns[pat]['type'] != pattype:
-                #     continue
+                if 0 not in algos.get('EMatch', {}) or exclude_gj_index not in algos.get('GenericJoin', {}):
+                    continue
+                if category and algos['EMatch'][0][0]['category'] != category:
+                    continue
                 em_row = min(algos['EMatch'][0], key=get_time)
                 gj_row = min(algos['GenericJoin'][exclude_gj_index], key=get_time)
 
@@ -143,7 +182,8 @@ for bench, sizes in sorted(benches.items()):
             # fracs = [em / gj for gj, em in zip(gj_times_no_timeout, em_times_no_timeout)]
             hmean = harmonic_mean(fracs)
             gmean = geometric_mean(fracs)
-            print(f'{exclude_gj_index}, {bench:>10}, {size:>10}, {gj_faster:>3}, {em_faster:>3},  {em_timeout}, ' +
+            type_column = f'{category:>9}, ' if category else ''
+            print(f'{exclude_gj_index}, {bench:>10}, {size:>10}, {type_column}{gj_faster:>3}, {em_faster:>3},  {em_timeout}, ' +
                 f'{fmt_x(total)},  {fmt_x(hmean)},  {fmt_x(gmean)},  {fmt_x(max(fracs))},  {fmt_x(median(fracs))},  {fmt_x(min(fracs))}')
 
 
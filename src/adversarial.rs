@@ -0,0 +1,86 @@
+//! Generated e-graphs that trigger the worst cases of top-down e-matching.
+//!
+//! For a parameter n the e-graph has leaves `a0 .. a(n-1)` and
+//!
+//! - a class `G` holding `(g ai)` for every leaf, and a chain of classes
+//!   `F1 = (f G G)`, `F2 = (f F1 F1)`, ... Repeated variables such as
+//!   `(f (g ?x) (g ?x))` make EM enumerate all n^2 pairs of `G`'s nodes
+//!   before discarding all but n of them, and every level of nesting
+//!   multiplies that again.
+//! - a class `E` holding `(r ai aj)` for the edges of a circulant graph with
+//!   about n * sqrt(n) edges, under roots `(t E E E)` and `(q E E E E)`.
+//!   Cyclic queries like the triangle `(t (r ?x ?y) (r ?y ?z) (r ?z ?x))`
+//!   take EM cubic time in the number of edges, while GJ stays within the
+//!   AGM bound.
+
+use crate::*;
+use egg::{define_language, Id, Symbol};
+
+pub fn adversarial_bench() -> Result<Bench<Adversarial, ()>, Vec<String>> {
+    Ok(Bench {
+        name: "adversarial".into(),
+        start_exprs: vec![],
+        rules: vec![],
+        bench_pats: parse_patterns("adversarial")?,
+        conditionals: vec![],
+        has_data: None,
+    })
+}
+
+define_language! {
+    pub enum Adversarial {
+        "f" = F([Id; 2]),
+        "g" = G(Id),
+        "r" = R([Id; 2]),
+        "t" = T([Id; 3]),
+        "q" = Q([Id; 4]),
+        Symbol(Symbol),
+    }
+}
+
+type EGraph = egg::EGraph<Adversarial, ()>;
+
+/// How many `f` levels to stack on top of `G`.
+const DEPTH: usize = 3;
+
+fn union_all(egraph: &mut EGraph, ids: &[Id]) -> Id {
+    let mut root = ids[0];
+    for id in &ids[1..] {
+        root = egraph.union(root, *id).0;
+    }
+    root
+}
+
+pub fn generate(n: usize) -> EGraph {
+    assert!(n > 0, "adversarial e-graphs need n > 0");
+    let mut egraph = EGraph::default();
+    let leaves: Vec<Id> = (0..n)
+        .map(|i| egraph.add(Adversarial::Symbol(format!("a{}", i).into())))
+        .collect();
+
+    let gs: Vec<Id> = leaves
+        .iter()
+        .map(|a| egraph.add(Adversarial::G(*a)))
+        .collect();
+    let mut f = union_all(&mut egraph, &gs);
+    for _ in 0..DEPTH {
+        f = egraph.add(Adversarial::F([f, f]));
+    }
+
+    // each vertex is connected to its nearest sqrt(n) neighbours on both sides
+    let degree = ((n as f64).sqrt() as usize).max(1);
+    let mut edges = vec![];
+    for i in 0..n {
+        for k in 1..=degree {
+            let j = (i + k) % n;
+            edges.push(egraph.add(Adversarial::R([leaves[i], leaves[j]])));
+            edges.push(egraph.add(Adversarial::R([leaves[j], leaves[i]])));
+        }
+    }
+    let e = union_all(&mut egraph, &edges);
+    egraph.add(Adversarial::T([e, e, e]));
+    egraph.add(Adversarial::Q([e, e, e, e]));
+
+    egraph.rebuild();
+    egraph
+}
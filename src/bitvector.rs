@@ -0,0 +1,490 @@
+use crate::*;
+use egg::{define_language, rewrite as rw, Id, Symbol};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+pub fn bitvector_bench() -> Result<Bench<Bv, KnownBits>, Vec<String>> {
+    let (rules, conditionals) = rules();
+    Ok(Bench {
+        name: "bitvector".into(),
+        start_exprs: [
+            // zero-extend of a truncate, spelled with shifts
+            "(and (lshr (shl x:32 24:32) 24:32) 255:32)",
+            // strength reduction candidates
+            "(add (mul x:32 8:32) (mul x:32 8:32))",
+            "(mul (add x:32 1:32) 4:32)",
+            // negation through xor
+            "(sub (xor x:32 0xffffffff:32) 0xffffffff:32)",
+            // first step of a popcount
+            "(add (and x:32 0x55555555:32) (and (lshr x:32 1:32) 0x55555555:32))",
+            // branchless absolute value
+            "(sub (xor x:32 (lshr x:32 31:32)) (lshr x:32 31:32))",
+            // rotate and masks
+            "(or (shl x:8 4:8) (lshr x:8 4:8))",
+            "(and (or x:64 0xff00:64) 0xff:64)",
+            "(xor (and a:16 b:16) (and a:16 (not b:16)))",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rules,
+        bench_pats: parse_patterns("bitvector")?,
+        conditionals,
+        has_data: Some(|data| data.zeros | data.ones != 0),
+    })
+}
+
+/// A constant written `value:width`, like `255:8` or `0xff:8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bits {
+    value: u64,
+    width: u32,
+}
+
+fn mask(width: u32) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+impl Bits {
+    fn new(value: u64, width: u32) -> Self {
+        Bits {
+            value: value & mask(width),
+            width,
+        }
+    }
+}
+
+impl FromStr for Bits {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, width) = s.split_once(':').ok_or("expected value:width")?;
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map_err(|e| e.to_string())?;
+        let width: u32 = width.parse().map_err(|e: std::num::ParseIntError| e.to_string())?;
+        if width == 0 || width > 64 || value & !mask(width) != 0 {
+            return Err(format!("{} doesn't fit in {} bits", value, width));
+        }
+        Ok(Bits { value, width })
+    }
+}
+
+impl fmt::Display for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.value, self.width)
+    }
+}
+
+define_language! {
+    pub enum Bv {
+        "add" = Add([Id; 2]),
+        "sub" = Sub([Id; 2]),
+        "mul" = Mul([Id; 2]),
+        "shl" = Shl([Id; 2]),
+        "lshr" = Lshr([Id; 2]),
+        "and" = And([Id; 2]),
+        "or" = Or([Id; 2]),
+        "xor" = Xor([Id; 2]),
+        "not" = Not(Id),
+
+        Const(Bits),
+        // variables carry their width the same way, as in `x:32`
+        Symbol(Symbol),
+    }
+}
+
+type EGraph = egg::EGraph<Bv, KnownBits>;
+
+#[derive(Default, Clone)]
+pub struct KnownBits;
+
+/// The width of an e-class and which of its bits are known to be zero or
+/// one. An e-class is a constant once every bit is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    width: Option<u32>,
+    zeros: u64,
+    ones: u64,
+}
+
+impl Data {
+    fn unknown(width: Option<u32>) -> Self {
+        Data {
+            width,
+            zeros: 0,
+            ones: 0,
+        }
+    }
+
+    fn constant(&self) -> Option<Bits> {
+        let width = self.width?;
+        if (self.zeros | self.ones) == mask(width) {
+            Some(Bits::new(self.ones, width))
+        } else {
+            None
+        }
+    }
+
+    fn known_trailing_zeros(&self) -> u32 {
+        self.zeros.trailing_ones()
+    }
+}
+
+/// What is known of `a` shifted by `k`, with the width of `a`.
+fn shifted(a: &Data, k: u64, left: bool) -> Data {
+    let width = match a.width {
+        Some(width) => width,
+        None => return Data::unknown(None),
+    };
+    let m = mask(width);
+    if k >= width as u64 {
+        return Data {
+            width: a.width,
+            zeros: m,
+            ones: 0,
+        };
+    }
+    let (zeros, ones) = if left {
+        (((a.zeros << k) | mask(k as u32)) & m, (a.ones << k) & m)
+    } else {
+        ((a.zeros >> k) | (m & !(m >> k)), a.ones >> k)
+    };
+    Data {
+        width: a.width,
+        zeros,
+        ones,
+    }
+}
+
+impl Analysis<Bv> for KnownBits {
+    type Data = Data;
+
+    fn make(egraph: &EGraph, enode: &Bv) -> Data {
+        let x = |i: &Id| &egraph[*i].data;
+        let c = |i: &Id| egraph[*i].data.constant();
+        let mut width = None;
+        enode.for_each(|i| width = width.or(egraph[i].data.width));
+        let folded = |v: u64| {
+            let b = Bits::new(v, width.unwrap());
+            Data {
+                width,
+                zeros: !b.value & mask(b.width),
+                ones: b.value,
+            }
+        };
+        match enode {
+            Bv::Const(b) => Data {
+                width: Some(b.width),
+                zeros: !b.value & mask(b.width),
+                ones: b.value,
+            },
+            Bv::Symbol(s) => {
+                let s = s.to_string();
+                let width = s.split_once(':').and_then(|(_, w)| w.parse().ok());
+                Data::unknown(width)
+            }
+            _ if width.is_none() => Data::unknown(None),
+            Bv::And([a, b]) => Data {
+                width,
+                zeros: x(a).zeros | x(b).zeros,
+                ones: x(a).ones & x(b).ones,
+            },
+            Bv::Or([a, b]) => Data {
+                width,
+                zeros: x(a).zeros & x(b).zeros,
+                ones: x(a).ones | x(b).ones,
+            },
+            Bv::Xor([a, b]) => {
+                let known = (x(a).zeros | x(a).ones) & (x(b).zeros | x(b).ones);
+                let value = x(a).ones ^ x(b).ones;
+                Data {
+                    width,
+                    zeros: !value & known,
+                    ones: value & known,
+                }
+            }
+            Bv::Not(a) => Data {
+                width,
+                zeros: x(a).ones,
+                ones: x(a).zeros,
+            },
+            Bv::Shl([a, k]) | Bv::Lshr([a, k]) if c(k).is_some() => {
+                shifted(x(a), c(k).unwrap().value, matches!(enode, Bv::Shl(_)))
+            }
+            // the amount may have another width than the shifted operand
+            Bv::Shl([a, _]) | Bv::Lshr([a, _]) => Data::unknown(x(a).width),
+            Bv::Add([a, b]) => match (c(a), c(b)) {
+                (Some(a), Some(b)) => folded(a.value.wrapping_add(b.value)),
+                _ => {
+                    // the sum of multiples of 2^k is a multiple of 2^k
+                    let tz = x(a).known_trailing_zeros().min(x(b).known_trailing_zeros());
+                    Data {
+                        width,
+                        zeros: mask(tz),
+                        ones: 0,
+                    }
+                }
+            },
+            Bv::Sub([a, b]) => match (c(a), c(b)) {
+                (Some(a), Some(b)) => folded(a.value.wrapping_sub(b.value)),
+                _ => Data::unknown(width),
+            },
+            Bv::Mul([a, b]) => match (c(a), c(b)) {
+                (Some(a), Some(b)) => folded(a.value.wrapping_mul(b.value)),
+                _ => {
+                    let tz = x(a).known_trailing_zeros() + x(b).known_trailing_zeros();
+                    Data {
+                        width,
+                        zeros: mask(tz.min(width.unwrap())),
+                        ones: 0,
+                    }
+                }
+            },
+            _ => Data::unknown(width),
+        }
+    }
+
+    fn merge(&self, to: &mut Data, from: Data) -> Option<Ordering> {
+        let before = to.clone();
+        to.width = to.width.or(from.width);
+        to.zeros |= from.zeros;
+        to.ones |= from.ones;
+        match (*to == before, *to == from) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+
+    fn modify(egraph: &mut EGraph, id: Id) {
+        if let Some(b) = egraph[id].data.constant() {
+            let const_id = egraph.add(Bv::Const(b));
+            egraph.union(id, const_id);
+        }
+    }
+}
+
+fn var(s: &str) -> Var {
+    s.parse().unwrap()
+}
+
+fn constant(egraph: &EGraph, subst: &Subst, v: Var) -> Option<Bits> {
+    egraph[subst[v]].data.constant()
+}
+
+fn zero_class(egraph: &EGraph, id: Id) -> bool {
+    matches!(egraph[id].data.constant(), Some(b) if b.value == 0)
+}
+
+fn one_class(egraph: &EGraph, id: Id) -> bool {
+    matches!(egraph[id].data.constant(), Some(b) if b.value == 1)
+}
+
+fn all_ones_class(egraph: &EGraph, id: Id) -> bool {
+    matches!(egraph[id].data.constant(), Some(b) if b.value == mask(b.width))
+}
+
+/// Both shift amounts are constants that add up to less than the width.
+fn shifts_in_range(c1: &str, c2: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let c1 = var(c1);
+    let c2 = var(c2);
+    move |egraph, _, subst| match (constant(egraph, subst, c1), constant(egraph, subst, c2)) {
+        (Some(a), Some(b)) => matches!(a.value.checked_add(b.value), Some(s) if s < a.width as u64),
+        _ => false,
+    }
+}
+
+/// Every bit that the mask `c` clears is already known to be zero in `x`.
+fn and_is_redundant(x: &str, c: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let x = var(x);
+    let c = var(c);
+    move |egraph, _, subst| match constant(egraph, subst, c) {
+        Some(b) => (egraph[subst[x]].data.zeros | b.value) == mask(b.width),
+        None => false,
+    }
+}
+
+/// Every bit that `c` sets is already known to be one in `x`.
+fn or_is_redundant(x: &str, c: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let x = var(x);
+    let c = var(c);
+    move |egraph, _, subst| match constant(egraph, subst, c) {
+        Some(b) => egraph[subst[x]].data.ones & b.value == b.value,
+        None => false,
+    }
+}
+
+type ComputeConst = dyn Fn(&EGraph, &Subst) -> Option<Bits> + Send + Sync;
+
+/// Computes a constant from the match, binds it to `name` and applies `then`.
+/// Nothing is applied when `compute` returns `None`.
+struct WithConst {
+    name: Var,
+    compute: Box<ComputeConst>,
+    then: Pattern<Bv>,
+}
+
+impl Applier<Bv, KnownBits> for WithConst {
+    fn apply_one(&self, egraph: &mut EGraph, eclass: Id, subst: &Subst) -> Vec<Id> {
+        match (self.compute)(egraph, subst) {
+            Some(b) => {
+                let mut subst = subst.clone();
+                subst.insert(self.name, egraph.add(Bv::Const(b)));
+                self.then.apply_one(egraph, eclass, &subst)
+            }
+            None => vec![],
+        }
+    }
+}
+
+fn with_const(
+    name: &str,
+    compute: impl Fn(&EGraph, &Subst) -> Option<Bits> + Send + Sync + 'static,
+    then: &str,
+) -> WithConst {
+    WithConst {
+        name: var(name),
+        compute: Box::new(compute),
+        then: then.parse().unwrap(),
+    }
+}
+
+/// A constant of the same width as `like`, computed from that width.
+fn const_like(like: &str, f: fn(u32) -> u64) -> impl Fn(&EGraph, &Subst) -> Option<Bits> {
+    let like = var(like);
+    move |egraph, subst| {
+        let width = egraph[subst[like]].data.width?;
+        Some(Bits::new(f(width), width))
+    }
+}
+
+/// Applies `f` to the constant bound to `v`.
+fn map_const(v: &str, f: fn(Bits) -> Option<u64>) -> impl Fn(&EGraph, &Subst) -> Option<Bits> {
+    let v = var(v);
+    move |egraph, subst| {
+        let b = constant(egraph, subst, v)?;
+        Some(Bits::new(f(b)?, b.width))
+    }
+}
+
+fn log2(b: Bits) -> Option<u64> {
+    if b.value.is_power_of_two() {
+        Some(b.value.trailing_zeros() as u64)
+    } else {
+        None
+    }
+}
+
+fn low_mask(b: Bits) -> Option<u64> {
+    if b.value >= b.width as u64 {
+        Some(0)
+    } else {
+        Some(mask(b.width) >> b.value)
+    }
+}
+
+fn high_mask(b: Bits) -> Option<u64> {
+    if b.value >= b.width as u64 {
+        Some(0)
+    } else {
+        Some((mask(b.width) << b.value) & mask(b.width))
+    }
+}
+
+/// The rules, and the searchers of the conditional ones for `--conditional`.
+#[rustfmt::skip]
+pub fn rules() -> (Vec<Rewrite<Bv, KnownBits>>, Vec<ConditionalSearcher<Bv, KnownBits>>) {
+    let mut c = Conditionals::default();
+    let rules = vec![
+        rw!("add-comm";  "(add ?x ?y)"          => "(add ?y ?x)"),
+        rw!("mul-comm";  "(mul ?x ?y)"          => "(mul ?y ?x)"),
+        rw!("and-comm";  "(and ?x ?y)"          => "(and ?y ?x)"),
+        rw!("or-comm";   "(or ?x ?y)"           => "(or ?y ?x)"),
+        rw!("xor-comm";  "(xor ?x ?y)"          => "(xor ?y ?x)"),
+        rw!("add-assoc"; "(add ?x (add ?y ?z))" => "(add (add ?x ?y) ?z)"),
+        rw!("mul-assoc"; "(mul ?x (mul ?y ?z))" => "(mul (mul ?x ?y) ?z)"),
+        rw!("and-assoc"; "(and ?x (and ?y ?z))" => "(and (and ?x ?y) ?z)"),
+        rw!("or-assoc";  "(or ?x (or ?y ?z))"   => "(or (or ?x ?y) ?z)"),
+        rw!("xor-assoc"; "(xor ?x (xor ?y ?z))" => "(xor (xor ?x ?y) ?z)"),
+
+        c.add(ConditionalSearcher::new("add-zero", "(add ?x ?c)").guard("?c", zero_class))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("mul-one", "(mul ?x ?c)").guard("?c", one_class))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("mul-zero", "(mul ?x ?c)").guard("?c", zero_class))
+            .rewrite("?c"),
+        c.add(ConditionalSearcher::new("and-zero", "(and ?x ?c)").guard("?c", zero_class))
+            .rewrite("?c"),
+        c.add(ConditionalSearcher::new("and-ones", "(and ?x ?c)").guard("?c", all_ones_class))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("or-zero", "(or ?x ?c)").guard("?c", zero_class))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("or-ones", "(or ?x ?c)").guard("?c", all_ones_class))
+            .rewrite("?c"),
+        c.add(ConditionalSearcher::new("xor-zero", "(xor ?x ?c)").guard("?c", zero_class))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("xor-ones", "(xor ?x ?c)").guard("?c", all_ones_class))
+            .rewrite("(not ?x)"),
+        c.add(ConditionalSearcher::new("shl-zero", "(shl ?x ?c)").guard("?c", zero_class))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("lshr-zero", "(lshr ?x ?c)").guard("?c", zero_class))
+            .rewrite("?x"),
+
+        rw!("and-self"; "(and ?x ?x)" => "?x"),
+        rw!("or-self";  "(or ?x ?x)"  => "?x"),
+        rw!("not-not";  "(not (not ?x))" => "?x"),
+        rw!("sub-self";  "(sub ?x ?x)"       => { with_const("?z", const_like("?x", |_| 0), "?z") }),
+        rw!("xor-self";  "(xor ?x ?x)"       => { with_const("?z", const_like("?x", |_| 0), "?z") }),
+        rw!("and-compl"; "(and ?x (not ?x))" => { with_const("?z", const_like("?x", |_| 0), "?z") }),
+        rw!("or-compl";  "(or ?x (not ?x))"  => { with_const("?m", const_like("?x", mask), "?m") }),
+        rw!("xor-compl"; "(xor ?x (not ?x))" => { with_const("?m", const_like("?x", mask), "?m") }),
+
+        rw!("demorgan-and"; "(and (not ?x) (not ?y))" => "(not (or ?x ?y))"),
+        rw!("demorgan-or";  "(or (not ?x) (not ?y))"  => "(not (and ?x ?y))"),
+        rw!("add-sub";  "(add (sub ?x ?y) ?y)" => "?x"),
+        rw!("sub-add";  "(sub (add ?x ?y) ?y)" => "?x"),
+        rw!("sub-const"; "(sub ?x ?c)" => {
+            with_const("?n", map_const("?c", |b| Some(b.value.wrapping_neg())), "(add ?x ?n)")
+        }),
+        rw!("mul-dist";   "(mul (add ?x ?y) ?z)"          => "(add (mul ?x ?z) (mul ?y ?z))"),
+        rw!("mul-factor"; "(add (mul ?x ?z) (mul ?y ?z))" => "(mul (add ?x ?y) ?z)"),
+        rw!("and-or-dist"; "(and (or ?x ?y) ?z)" => "(or (and ?x ?z) (and ?y ?z))"),
+        rw!("xor-and";     "(xor (and ?x ?y) (and ?x ?z))" => "(and ?x (xor ?y ?z))"),
+
+        rw!("mul-pow2"; "(mul ?x ?c)" => { with_const("?k", map_const("?c", log2), "(shl ?x ?k)") }),
+        rw!("add-self"; "(add ?x ?x)" => { with_const("?k", const_like("?x", |_| 1), "(shl ?x ?k)") }),
+        c.add(ConditionalSearcher::new("shl-shl", "(shl (shl ?x ?c1) ?c2)")
+            .condition(shifts_in_range("?c1", "?c2")))
+            .rewrite("(shl ?x (add ?c1 ?c2))"),
+        c.add(ConditionalSearcher::new("lshr-lshr", "(lshr (lshr ?x ?c1) ?c2)")
+            .condition(shifts_in_range("?c1", "?c2")))
+            .rewrite("(lshr ?x (add ?c1 ?c2))"),
+        rw!("lshr-shl"; "(lshr (shl ?x ?c) ?c)" => {
+            with_const("?m", map_const("?c", low_mask), "(and ?x ?m)")
+        }),
+        rw!("shl-lshr"; "(shl (lshr ?x ?c) ?c)" => {
+            with_const("?m", map_const("?c", high_mask), "(and ?x ?m)")
+        }),
+        rw!("shl-and"; "(shl (and ?x ?y) ?c)" => "(and (shl ?x ?c) (shl ?y ?c))"),
+        rw!("lshr-and"; "(lshr (and ?x ?y) ?c)" => "(and (lshr ?x ?c) (lshr ?y ?c))"),
+
+        c.add(ConditionalSearcher::new("and-known", "(and ?x ?c)")
+            .condition(and_is_redundant("?x", "?c")))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("or-known", "(or ?x ?c)").condition(or_is_redundant("?x", "?c")))
+            .rewrite("?x"),
+        rw!("and-and-const";
+            "(and (and ?x ?c1) ?c2)" => "(and ?x (and ?c1 ?c2))"),
+        rw!("or-and-const";
+            "(and (or ?x ?c1) ?c2)" => "(or (and ?x ?c2) (and ?c1 ?c2))"),
+    ];
+    (rules, c.into_searchers())
+}
@@ -0,0 +1,135 @@
+use crate::*;
+use egg::{define_language, rewrite as rw, Id, Symbol};
+use std::cmp::Ordering;
+
+pub fn bool_bench() -> Result<Bench<Bool, ConstantFold>, Vec<String>> {
+    Ok(Bench {
+        name: "bool".into(),
+        start_exprs: [
+            // full adder: sum and carry-out
+            "(^ (^ a b) cin)",
+            "(| (& a b) (& cin (^ a b)))",
+            // 2-bit ripple-carry adder, second sum bit
+            "(^ (^ a1 b1) (| (& a0 b0) (& c0 (^ a0 b0))))",
+            // 2:1 and 4:1 multiplexers
+            "(ite s x y)",
+            "(| (& s0 (| (& s1 d3) (& (~ s1) d1))) (& (~ s0) (| (& s1 d2) (& (~ s1) d0))))",
+            // majority gate and equality comparator
+            "(| (| (& a b) (& b c)) (& a c))",
+            "(& (~ (^ a0 b0)) (~ (^ a1 b1)))",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rules: rules(),
+        bench_pats: parse_patterns("bool")?,
+        conditionals: vec![],
+        has_data: Some(|data| data.is_some()),
+    })
+}
+
+type EGraph = egg::EGraph<Bool, ConstantFold>;
+
+define_language! {
+    pub enum Bool {
+        "&" = And([Id; 2]),
+        "|" = Or([Id; 2]),
+        "^" = Xor([Id; 2]),
+        "~" = Not(Id),
+        "ite" = Ite([Id; 3]),
+
+        Const(bool),
+        Symbol(Symbol),
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct ConstantFold;
+impl Analysis<Bool> for ConstantFold {
+    type Data = Option<bool>;
+
+    fn make(egraph: &EGraph, enode: &Bool) -> Self::Data {
+        let x = |i: &Id| egraph[*i].data;
+        Some(match enode {
+            Bool::Const(c) => *c,
+            Bool::And([a, b]) => x(a)? && x(b)?,
+            Bool::Or([a, b]) => x(a)? || x(b)?,
+            Bool::Xor([a, b]) => x(a)? ^ x(b)?,
+            Bool::Not(a) => !x(a)?,
+            Bool::Ite([c, t, e]) => {
+                if x(c)? {
+                    x(t)?
+                } else {
+                    x(e)?
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    fn merge(&self, a: &mut Self::Data, b: Self::Data) -> Option<Ordering> {
+        match (a.as_mut(), b) {
+            (None, None) => Some(Ordering::Equal),
+            (None, Some(_)) => {
+                *a = b;
+                Some(Ordering::Less)
+            }
+            (Some(_), None) => Some(Ordering::Greater),
+            (Some(_), Some(_)) => Some(Ordering::Equal),
+        }
+    }
+
+    fn modify(egraph: &mut EGraph, id: Id) {
+        if let Some(c) = egraph[id].data {
+            let added = egraph.add(Bool::Const(c));
+            egraph.union(id, added);
+        }
+    }
+}
+
+#[rustfmt::skip]
+pub fn rules() -> Vec<Rewrite<Bool, ConstantFold>> { vec![
+    rw!("comm-and";  "(& ?a ?b)"        => "(& ?b ?a)"),
+    rw!("comm-or";   "(| ?a ?b)"        => "(| ?b ?a)"),
+    rw!("comm-xor";  "(^ ?a ?b)"        => "(^ ?b ?a)"),
+    rw!("assoc-and"; "(& ?a (& ?b ?c))" => "(& (& ?a ?b) ?c)"),
+    rw!("assoc-or";  "(| ?a (| ?b ?c))" => "(| (| ?a ?b) ?c)"),
+    rw!("assoc-xor"; "(^ ?a (^ ?b ?c))" => "(^ (^ ?a ?b) ?c)"),
+
+    rw!("double-neg"; "(~ (~ ?a))" => "?a"),
+    rw!("demorgan-and"; "(~ (& ?a ?b))"     => "(| (~ ?a) (~ ?b))"),
+    rw!("demorgan-or";  "(~ (| ?a ?b))"     => "(& (~ ?a) (~ ?b))"),
+    rw!("and-demorgan"; "(| (~ ?a) (~ ?b))" => "(~ (& ?a ?b))"),
+    rw!("or-demorgan";  "(& (~ ?a) (~ ?b))" => "(~ (| ?a ?b))"),
+
+    rw!("dist-and-or"; "(& ?a (| ?b ?c))"        => "(| (& ?a ?b) (& ?a ?c))"),
+    rw!("dist-or-and"; "(| ?a (& ?b ?c))"        => "(& (| ?a ?b) (| ?a ?c))"),
+    rw!("factor-and";  "(| (& ?a ?b) (& ?a ?c))" => "(& ?a (| ?b ?c))"),
+    rw!("factor-or";   "(& (| ?a ?b) (| ?a ?c))" => "(| ?a (& ?b ?c))"),
+
+    rw!("absorb-and"; "(& ?a (| ?a ?b))" => "?a"),
+    rw!("absorb-or";  "(| ?a (& ?a ?b))" => "?a"),
+    rw!("idem-and";   "(& ?a ?a)"        => "?a"),
+    rw!("idem-or";    "(| ?a ?a)"        => "?a"),
+
+    rw!("and-true";  "(& ?a true)"  => "?a"),
+    rw!("and-false"; "(& ?a false)" => "false"),
+    rw!("or-true";   "(| ?a true)"  => "true"),
+    rw!("or-false";  "(| ?a false)" => "?a"),
+    rw!("and-compl"; "(& ?a (~ ?a))" => "false"),
+    rw!("or-compl";  "(| ?a (~ ?a))" => "true"),
+
+    rw!("xor-false"; "(^ ?a false)" => "?a"),
+    rw!("xor-true";  "(^ ?a true)"  => "(~ ?a)"),
+    rw!("xor-self";  "(^ ?a ?a)"    => "false"),
+    rw!("xor-compl"; "(^ ?a (~ ?a))" => "true"),
+    rw!("xor-not";   "(^ (~ ?a) (~ ?b))" => "(^ ?a ?b)"),
+    rw!("xor-expand";   "(^ ?a ?b)" => "(| (& ?a (~ ?b)) (& (~ ?a) ?b))"),
+    rw!("xor-collapse"; "(| (& ?a (~ ?b)) (& (~ ?a) ?b))" => "(^ ?a ?b)"),
+
+    rw!("ite-true";   "(ite true ?t ?e)"  => "?t"),
+    rw!("ite-false";  "(ite false ?t ?e)" => "?e"),
+    rw!("ite-same";   "(ite ?c ?t ?t)"    => "?t"),
+    rw!("ite-expand"; "(ite ?c ?t ?e)"    => "(| (& ?c ?t) (& (~ ?c) ?e))"),
+    rw!("ite-collapse"; "(| (& ?c ?t) (& (~ ?c) ?e))" => "(ite ?c ?t ?e)"),
+]}
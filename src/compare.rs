@@ -0,0 +1,415 @@
+//! The `compare-runs` subcommand: how the timings of a candidate run differ
+//! from a baseline run, e.g. before and after upgrading egg.
+//!
+//! Rows are matched on bench, e-graph, pattern, strategy and repeat. The
+//! e-graph is identified by its fingerprint when both runs recorded one,
+//! otherwise by its size. A pattern's ratio is the median candidate time over
+//! the median baseline time, across samples. It only counts as a change if
+//! it is further from 1 than `--noise` and, with several samples, the ranges
+//! of the two runs don't overlap. Searches that panicked, ran out of memory
+//! or were skipped have no time to compare; a pattern that fails in the
+//! candidate but not in the baseline is reported as a failure instead.
+//!
+//! Every row of the baseline should have a match in the candidate: if egg
+//! builds different e-graphs, nothing matches and there is nothing to
+//! compare, which is an error unless `--partial` allows it.
+
+use crate::report::{median, read_results, ResultRow};
+use crate::status::Status;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct CompareOpt {
+    /// Results file of the run to compare against
+    baseline: PathBuf,
+    /// Results file of the new run
+    candidate: PathBuf,
+    /// How long to assume timeouts took in seconds (default is the reported time)
+    #[structopt(long)]
+    timeout: Option<f64>,
+    /// Relative differences up to this are considered noise
+    #[structopt(long, default_value = "0.05")]
+    noise: f64,
+    /// Exit with an error if a pattern got significantly slower by more than this ratio
+    #[structopt(long, default_value = "1.25")]
+    threshold: f64,
+    /// Also list the patterns whose time didn't change
+    #[structopt(long)]
+    all: bool,
+    /// Allow rows of the baseline that the candidate doesn't have, e.g. when
+    /// it only ran some of the benches; some rows still have to match
+    #[structopt(long)]
+    partial: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    bench: String,
+    node_size: usize,
+    /// Empty unless both runs recorded fingerprints.
+    fingerprint: String,
+    pattern: String,
+    algo: String,
+    repeat_time: usize,
+}
+
+impl Key {
+    fn of(row: &ResultRow, by_fingerprint: bool) -> Self {
+        Key {
+            bench: row.bench(),
+            node_size: row.node_size,
+            fingerprint: if by_fingerprint {
+                row.fingerprint.clone()
+            } else {
+                String::new()
+            },
+            pattern: row.pattern.split_whitespace().collect::<Vec<_>>().join(" "),
+            algo: row.algo.clone(),
+            repeat_time: row.repeat_time,
+        }
+    }
+}
+
+/// The samples of one key in one run.
+#[derive(Debug, Default)]
+struct Samples {
+    /// Sorted times in microseconds.
+    times: Vec<f64>,
+    timeouts: usize,
+    result_sizes: Vec<String>,
+    /// The statuses of the samples that failed, without duplicates.
+    failures: Vec<Status>,
+}
+
+impl Samples {
+    fn min(&self) -> f64 {
+        self.times[0]
+    }
+
+    fn max(&self) -> f64 {
+        self.times[self.times.len() - 1]
+    }
+
+    fn failures(&self) -> String {
+        let failures: Vec<String> = self.failures.iter().map(|s| s.to_string()).collect();
+        failures.join("/")
+    }
+}
+
+fn group(rows: &[ResultRow], by_fingerprint: bool, timeout: Option<f64>) -> BTreeMap<Key, Samples> {
+    let mut groups: BTreeMap<Key, Samples> = BTreeMap::new();
+    for row in rows {
+        let samples = groups.entry(Key::of(row, by_fingerprint)).or_default();
+        if !row.status().is_measured() {
+            if !samples.failures.contains(&row.status()) {
+                samples.failures.push(row.status());
+            }
+            continue;
+        }
+        samples.times.push(row.micros(timeout));
+        if row.timed_out() {
+            samples.timeouts += 1;
+        } else if !samples.result_sizes.contains(&row.result_size) {
+            samples.result_sizes.push(row.result_size.clone());
+        }
+    }
+    for samples in groups.values_mut() {
+        samples.times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        samples.failures.sort();
+    }
+    groups
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Slower,
+    Faster,
+    Same,
+}
+
+/// The ratio of the median times and whether it is a change, or `None` if
+/// one of the runs has no measured sample.
+fn classify(base: &Samples, cand: &Samples, noise: f64) -> Option<(f64, Change)> {
+    if base.times.is_empty() || cand.times.is_empty() {
+        return None;
+    }
+    let ratio = median(&cand.times) / median(&base.times);
+    let overlap = cand.min() <= base.max() && base.min() <= cand.max();
+    let multiple = base.times.len() > 1 && cand.times.len() > 1;
+    let change = if (ratio - 1.0).abs() <= noise || (multiple && overlap) {
+        Change::Same
+    } else if ratio > 1.0 {
+        Change::Slower
+    } else {
+        Change::Faster
+    };
+    Some((ratio, change))
+}
+
+/// Compares two runs and exits with an error if the candidate is slower on
+/// some pattern by more than the threshold, fails where the baseline didn't,
+/// or doesn't cover the baseline.
+pub fn compare_runs(opt: &CompareOpt) {
+    let read = |file: &PathBuf| {
+        read_results(std::slice::from_ref(file)).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1)
+        })
+    };
+    let baseline = read(&opt.baseline);
+    let candidate = read(&opt.candidate);
+    let has_fingerprints = |rows: &[ResultRow]| rows.iter().all(|r| !r.fingerprint.is_empty());
+    let by_fingerprint = has_fingerprints(&baseline) && has_fingerprints(&candidate);
+    let baseline = group(&baseline, by_fingerprint, opt.timeout);
+    let mut candidate = group(&candidate, by_fingerprint, opt.timeout);
+
+    println!(
+        "{:>12}, {:>10}, {:>11}, {:>6}, {:>7}, {:>12}, {:>12}, pattern",
+        "bench", "size", "algo", "repeat", "ratio", "baseline", "candidate"
+    );
+    let mut ratios = vec![];
+    let mut counts = (0, 0, 0);
+    let mut size_changes = vec![];
+    let mut regressions = vec![];
+    let mut status_changes = vec![];
+    let mut new_failures = 0;
+    let mut matched = 0;
+    let mut only_baseline = 0;
+    for (key, base) in &baseline {
+        let cand = match candidate.remove(key) {
+            Some(cand) => cand,
+            None => {
+                only_baseline += 1;
+                continue;
+            }
+        };
+        matched += 1;
+        if base.failures != cand.failures {
+            if cand.failures.iter().any(|s| !base.failures.contains(s)) {
+                new_failures += 1;
+            }
+            let or_ok = |s: String| if s.is_empty() { "ok".to_string() } else { s };
+            status_changes.push((key.clone(), or_ok(base.failures()), or_ok(cand.failures())));
+        }
+        let (ratio, change) = match classify(base, &cand, opt.noise) {
+            Some(classified) => classified,
+            None => continue,
+        };
+        ratios.push(ratio);
+        match change {
+            Change::Slower => counts.0 += 1,
+            Change::Faster => counts.1 += 1,
+            Change::Same => counts.2 += 1,
+        }
+        if change == Change::Slower && ratio > opt.threshold {
+            regressions.push(key.clone());
+        }
+        if !(base.result_sizes.is_empty() || cand.result_sizes.is_empty())
+            && base.result_sizes != cand.result_sizes
+        {
+            size_changes.push((
+                key.clone(),
+                base.result_sizes.join("/"),
+                cand.result_sizes.join("/"),
+            ));
+        }
+
+        if change != Change::Same || opt.all {
+            let mark = match change {
+                Change::Slower => "+",
+                Change::Faster => "-",
+                Change::Same => " ",
+            };
+            let time = |s: &Samples| {
+                let to = if s.timeouts > 0 { " TO" } else { "" };
+                format!("{:.0}{}", median(&s.times), to)
+            };
+            println!(
+                "{:>12}, {:>10}, {:>11}, {:>6}, {}{:>6.2}, {:>12}, {:>12}, {}",
+                key.bench,
+                key.node_size,
+                key.algo,
+                key.repeat_time,
+                mark,
+                ratio,
+                time(base),
+                time(&cand),
+                key.pattern
+            );
+        }
+    }
+
+    println!();
+    println!(
+        "{} matched: {} slower, {} faster, {} unchanged (noise {:.0}%)",
+        ratios.len(),
+        counts.0,
+        counts.1,
+        counts.2,
+        opt.noise * 100.0
+    );
+    if !ratios.is_empty() {
+        let gmean = (ratios.iter().map(|r| r.ln()).sum::<f64>() / ratios.len() as f64).exp();
+        println!("geometric mean of candidate / baseline: {:.3}", gmean);
+    }
+    if only_baseline > 0 || !candidate.is_empty() {
+        println!(
+            "unmatched: {} only in the baseline, {} only in the candidate",
+            only_baseline,
+            candidate.len()
+        );
+    }
+    for (key, base, cand) in &size_changes {
+        println!(
+            "result_size changed from {} to {}: {} {} {} {}",
+            base, cand, key.bench, key.node_size, key.algo, key.pattern
+        );
+    }
+    for (key, base, cand) in &status_changes {
+        println!(
+            "status changed from {} to {}: {} {} {} {} {}",
+            base, cand, key.bench, key.node_size, key.algo, key.repeat_time, key.pattern
+        );
+    }
+
+    if new_failures > 0 {
+        eprintln!(
+            "error: {} searches fail only in the candidate",
+            new_failures
+        );
+    }
+    if !regressions.is_empty() {
+        eprintln!(
+            "error: {} patterns got slower by more than {}x",
+            regressions.len(),
+            opt.threshold
+        );
+    }
+    let uncovered = if matched == 0 {
+        eprintln!(
+            "error: none of the {} searches of the baseline match the candidate; \
+             did the e-graphs change?",
+            baseline.len()
+        );
+        true
+    } else if only_baseline > 0 && !opt.partial {
+        eprintln!(
+            "error: {} of the {} searches of the baseline have no match in the candidate; \
+             pass --partial if that's expected",
+            only_baseline,
+            baseline.len()
+        );
+        true
+    } else {
+        false
+    };
+    if new_failures > 0 || !regressions.is_empty() || uncovered {
+        std::process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(csv: &str) -> Vec<ResultRow> {
+        let header = "benchmark,node_size,fingerprint,algo,pattern,mode,status,time_ns,\
+                      result_size,repeat_time\n";
+        csv::Reader::from_reader(format!("{}{}", header, csv).as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    /// Samples of the given times in microseconds.
+    fn samples(times: &[f64]) -> Samples {
+        let mut times = times.to_vec();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Samples {
+            times,
+            ..Samples::default()
+        }
+    }
+
+    #[test]
+    fn keys() {
+        let rows = rows(
+            "math,1000,f1,EMatch,(+ ?a  ?b),pattern,ok,1000,10,0\n\
+             math,1000,f2,EMatch,(+ ?a ?b),pattern,ok,3000,10,0\n\
+             math,1000,f1,EMatch,(+ ?a ?b),post,ok,2000,10,0\n\
+             math,1000,f1,GenericJoin,(+ ?a ?b),pattern,timeout,5000,0,1\n\
+             math,1000,f1,GenericJoin,(+ ?a ?b),pattern,panic,0,0,1\n",
+        );
+        // the same e-graph size with different fingerprints is a different e-graph
+        let by_fingerprint = group(&rows, true, None);
+        assert_eq!(by_fingerprint.len(), 4);
+        let by_size = group(&rows, false, None);
+        assert_eq!(by_size.len(), 3);
+
+        let em = &by_size[&Key::of(&rows[0], false)];
+        assert_eq!(em.times, vec![1.0, 3.0]);
+        assert_eq!(em.result_sizes, vec!["10"]);
+        assert_eq!(by_size[&Key::of(&rows[2], false)].times, vec![2.0]);
+        assert_eq!(Key::of(&rows[2], false).bench, "math/post");
+
+        let gj = &by_size[&Key::of(&rows[3], false)];
+        assert_eq!(gj.times, vec![5.0]);
+        assert_eq!(gj.timeouts, 1);
+        assert!(gj.result_sizes.is_empty());
+        assert_eq!(gj.failures, vec![Status::Panic]);
+        // a timeout counts as the given timeout instead
+        assert_eq!(
+            group(&rows, false, Some(1.0))[&Key::of(&rows[3], false)].times,
+            vec![1e6]
+        );
+    }
+
+    #[test]
+    fn improved_and_regressed() {
+        let base = samples(&[100.0, 110.0, 120.0]);
+        let (ratio, change) = classify(&base, &samples(&[50.0, 55.0, 60.0]), 0.05).unwrap();
+        assert_eq!((ratio, change), (0.5, Change::Faster));
+        let (ratio, change) = classify(&base, &samples(&[200.0, 220.0, 240.0]), 0.05).unwrap();
+        assert_eq!((ratio, change), (2.0, Change::Slower));
+        // single samples have no range, so only the noise applies
+        let (_, change) = classify(&samples(&[100.0]), &samples(&[130.0]), 0.05).unwrap();
+        assert_eq!(change, Change::Slower);
+    }
+
+    #[test]
+    fn noisy() {
+        let base = samples(&[100.0, 110.0, 120.0]);
+        // within the noise
+        let (_, change) = classify(&base, &samples(&[104.0, 114.0, 124.0]), 0.05).unwrap();
+        assert_eq!(change, Change::Same);
+        // far from 1, but the ranges overlap
+        let (ratio, change) = classify(&base, &samples(&[60.0, 70.0, 115.0]), 0.05).unwrap();
+        assert!(ratio < 0.95);
+        assert_eq!(change, Change::Same);
+    }
+
+    #[test]
+    fn missing_on_one_side() {
+        let base = samples(&[100.0]);
+        let failed = Samples {
+            failures: vec![Status::Oom],
+            ..Samples::default()
+        };
+        assert_eq!(classify(&base, &failed, 0.05), None);
+        assert_eq!(classify(&failed, &base, 0.05), None);
+
+        let baseline = group(
+            &rows("math,1000,f,EMatch,(+ ?a ?b),pattern,ok,1000,10,0\n"),
+            true,
+            None,
+        );
+        let candidate = group(
+            &rows("math,2000,g,EMatch,(+ ?a ?b),pattern,ok,1000,10,0\n"),
+            true,
+            None,
+        );
+        assert!(baseline.keys().all(|key| !candidate.contains_key(key)));
+    }
+}
@@ -0,0 +1,299 @@
+//! Searching for the matches of a conditional rule, not only its left-hand side.
+//!
+//! egg keeps a rule's conditions inside its applier, out of reach of the
+//! harness, so suites define each conditional rule by its
+//! `ConditionalSearcher` and build the rule from it with `Conditionals`, which
+//! keeps the searchers for `--conditional`.
+
+use egg::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// A check on the e-class bound to a single variable, like `is_const`.
+pub type Guard<L, A> = dyn Fn(&EGraph<L, A>, Id) -> bool + Send + Sync;
+
+pub struct ConditionalSearcher<L: Language, A: Analysis<L>> {
+    pub name: String,
+    pub pattern: Pattern<L>,
+    guards: Vec<(Var, Arc<Guard<L, A>>)>,
+    conditions: Vec<Arc<dyn Condition<L, A> + Send + Sync>>,
+}
+
+// derived, it would require `A: Clone`
+impl<L: Language, A: Analysis<L>> Clone for ConditionalSearcher<L, A> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            pattern: self.pattern.clone(),
+            guards: self.guards.clone(),
+            conditions: self.conditions.clone(),
+        }
+    }
+}
+
+impl<L: Language, A: Analysis<L>> fmt::Debug for ConditionalSearcher<L, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConditionalSearcher")
+            .field("name", &self.name)
+            .field("pattern", &self.pattern.ast)
+            .field("guards", &self.guards.len())
+            .field("conditions", &self.conditions.len())
+            .finish()
+    }
+}
+
+impl<L: Language + FromOp, A: Analysis<L>> ConditionalSearcher<L, A> {
+    pub fn new(name: &str, pattern: &str) -> Self {
+        Self {
+            name: name.into(),
+            pattern: pattern.parse().unwrap(),
+            guards: vec![],
+            conditions: vec![],
+        }
+    }
+
+    /// Only keep matches where the class bound to `var` passes `guard`.
+    pub fn guard(
+        mut self,
+        var: &str,
+        guard: impl Fn(&EGraph<L, A>, Id) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.guards.push((var.parse().unwrap(), Arc::new(guard)));
+        self
+    }
+
+    /// Only keep matches that pass `condition`, checked after the guards.
+    pub fn condition(mut self, condition: impl Condition<L, A> + Send + Sync + 'static) -> Self {
+        self.conditions.push(Arc::new(condition));
+        self
+    }
+
+    /// The rule rewriting the matches that pass every guard and condition to
+    /// `rhs`.
+    pub fn rewrite(&self, rhs: &str) -> Rewrite<L, A>
+    where
+        L: Send + Sync + 'static,
+        A: 'static,
+    {
+        self.rewrite_with(rhs.parse::<Pattern<L>>().unwrap())
+    }
+
+    /// Like `rewrite`, with any applier as the right-hand side.
+    pub fn rewrite_with(&self, applier: impl Applier<L, A> + Send + Sync + 'static) -> Rewrite<L, A>
+    where
+        L: Send + Sync + 'static,
+        A: 'static,
+    {
+        let pattern = self.pattern.clone();
+        rewrite!(self.name.clone(); { pattern } => { applier } if self.clone())
+    }
+
+    /// Returns how many matches the search finds and how many of them pass
+    /// every guard and condition.
+    pub fn search(&self, egraph: &mut EGraph<L, A>) -> (usize, usize) {
+        let matches = self.pattern.search(egraph);
+        let mut found = 0;
+        let mut survived = 0;
+        for m in &matches {
+            for subst in &m.substs {
+                found += 1;
+                if self.check(egraph, m.eclass, subst) {
+                    survived += 1;
+                }
+            }
+        }
+        (found, survived)
+    }
+
+    /// A copy of `egraph` to search instead, with the guards pushed into it
+    /// where possible: an e-node is left out when it could only match an atom
+    /// with a guarded variable bound to a failing class, a semijoin on that
+    /// atom's relation (see `atom`). The guards are still checked on the
+    /// matches, so the search finds the same survivors either way.
+    ///
+    /// The copy is built by adding the kept e-nodes bottom-up, so its memo,
+    /// parents and analysis data are its own. A class whose terms all go
+    /// through left out e-nodes keeps its failing e-nodes instead, as the
+    /// matches that bind a variable to it would be lost otherwise.
+    pub fn pushdown(&self, egraph: &EGraph<L, A>) -> EGraph<L, A>
+    where
+        A: Clone,
+    {
+        let pushed: Vec<(L, usize, &Guard<L, A>)> = self
+            .guards
+            .iter()
+            .filter_map(|(var, guard)| {
+                let (op, index) = self.atom(*var)?;
+                Some((op, index, guard.as_ref()))
+            })
+            .collect();
+        let passes = |node: &L| {
+            pushed.iter().all(|(op, index, guard)| {
+                !node.matches(op) || guard(egraph, node.children()[*index])
+            })
+        };
+
+        let mut copy = EGraph::new(egraph.analysis.clone());
+        let mut ids = HashMap::new();
+        let mut pending: Vec<(Id, &L)> = egraph
+            .classes()
+            .flat_map(|class| class.nodes.iter().map(move |node| (class.id, node)))
+            .collect();
+        loop {
+            let before = pending.len();
+            pending.retain(|&(class, node)| {
+                let added = node.all(|c| ids.contains_key(&egraph.find(c))) && passes(node);
+                if added {
+                    add_node(&mut copy, &mut ids, egraph, class, node);
+                }
+                !added
+            });
+            if pending.len() < before {
+                continue;
+            }
+            // only failing e-nodes and the ones above them are left
+            let mut rescued = false;
+            pending.retain(|&(class, node)| {
+                let rescue =
+                    !ids.contains_key(&class) && node.all(|c| ids.contains_key(&egraph.find(c)));
+                if rescue {
+                    add_node(&mut copy, &mut ids, egraph, class, node);
+                    rescued = true;
+                }
+                !rescue
+            });
+            if !rescued {
+                break;
+            }
+        }
+        copy.rebuild();
+        copy
+    }
+
+    /// The operator and child of the first atom that has `var` as a child,
+    /// if a guard on `var` can be pushed into that atom's relation. This is
+    /// only sound if every atom with the same operator has `var` at the same
+    /// child, as the relation is shared between them.
+    fn atom(&self, var: Var) -> Option<(L, usize)> {
+        let ast = self.pattern.ast.as_ref();
+        let holds_var = |child: &Id| ast[usize::from(*child)] == ENodeOrVar::Var(var);
+        let (op, index) = ast.iter().find_map(|node| match node {
+            ENodeOrVar::ENode(n) => n.children().iter().position(holds_var).map(|i| (n, i)),
+            ENodeOrVar::Var(_) => None,
+        })?;
+        let shared = ast.iter().any(|node| {
+            matches!(node, ENodeOrVar::ENode(n) if n.matches(op) && !holds_var(&n.children()[index]))
+        });
+        if shared {
+            None
+        } else {
+            Some((op.clone(), index))
+        }
+    }
+}
+
+/// Adds `node` of the class `class` of `egraph` to `copy`, with its children
+/// mapped by `ids` from the classes of `egraph` to those of `copy`.
+fn add_node<L: Language, A: Analysis<L>>(
+    copy: &mut EGraph<L, A>,
+    ids: &mut HashMap<Id, Id>,
+    egraph: &EGraph<L, A>,
+    class: Id,
+    node: &L,
+) {
+    let new = copy.add(node.clone().map_children(|c| ids[&egraph.find(c)]));
+    match ids.get(&class) {
+        Some(&id) => {
+            copy.union(id, new);
+        }
+        None => {
+            ids.insert(class, new);
+        }
+    }
+}
+
+/// Checks the guards and conditions of a match when the rule is applied.
+impl<L: Language, A: Analysis<L>> Condition<L, A> for ConditionalSearcher<L, A> {
+    fn check(&self, egraph: &mut EGraph<L, A>, eclass: Id, subst: &Subst) -> bool {
+        self.guards.iter().all(|(v, g)| g(egraph, subst[*v]))
+            && self
+                .conditions
+                .iter()
+                .all(|c| c.check(egraph, eclass, subst))
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        let guarded = self.guards.iter().map(|(v, _)| *v);
+        guarded
+            .chain(self.conditions.iter().flat_map(|c| c.vars()))
+            .collect()
+    }
+}
+
+/// The searchers of a suite's conditional rules, collected while its rules
+/// are built from them with `c.add(searcher).rewrite(rhs)`.
+pub struct Conditionals<L: Language, A: Analysis<L>> {
+    searchers: Vec<ConditionalSearcher<L, A>>,
+}
+
+impl<L: Language, A: Analysis<L>> Default for Conditionals<L, A> {
+    fn default() -> Self {
+        Self { searchers: vec![] }
+    }
+}
+
+impl<L: Language, A: Analysis<L>> Conditionals<L, A> {
+    /// Keeps `searcher` and returns it to build its rule from.
+    pub fn add(&mut self, searcher: ConditionalSearcher<L, A>) -> &ConditionalSearcher<L, A> {
+        self.searchers.push(searcher);
+        self.searchers.last().unwrap()
+    }
+
+    /// The searchers in the order they were added.
+    pub fn into_searchers(self) -> Vec<ConditionalSearcher<L, A>> {
+        self.searchers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_zero(egraph: &EGraph<SymbolLang, ()>, id: Id) -> bool {
+        !egraph[id].nodes.iter().any(|n| n.op.as_str() == "0")
+    }
+
+    #[test]
+    fn pushdown_leaves_out_failing_nodes_but_keeps_their_matches() {
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        // the class of `(f a 0)` has no other term, and `(f (f a 0) 1)` binds ?x to it
+        egraph.add_expr(&"(f a 0)".parse().unwrap());
+        egraph.add_expr(&"(f (f a 0) 1)".parse().unwrap());
+        let dropped = egraph.add_expr(&"(f c 0)".parse().unwrap());
+        let d = egraph.add_expr(&"d".parse().unwrap());
+        egraph.union(dropped, d);
+        egraph.rebuild();
+
+        let searcher = ConditionalSearcher::new("f", "(f ?x ?y)").guard("?y", not_zero);
+        assert_eq!(searcher.search(&mut egraph), (3, 1));
+        let mut copy = searcher.pushdown(&egraph);
+        assert_eq!(copy.number_of_classes(), egraph.number_of_classes());
+        assert_eq!(
+            copy.total_number_of_nodes(),
+            egraph.total_number_of_nodes() - 1
+        );
+        assert_eq!(searcher.search(&mut copy), (2, 1));
+    }
+
+    #[test]
+    fn guard_is_not_pushed_into_shared_relation() {
+        let searcher: ConditionalSearcher<SymbolLang, ()> =
+            ConditionalSearcher::new("f", "(f ?x (f ?y ?x))").guard("?y", not_zero);
+        assert_eq!(searcher.atom("?y".parse().unwrap()), None);
+        let searcher: ConditionalSearcher<SymbolLang, ()> =
+            ConditionalSearcher::new("f", "(f ?x (g ?y))").guard("?y", not_zero);
+        let (op, index) = searcher.atom("?y".parse().unwrap()).unwrap();
+        assert_eq!((op.op.as_str(), index), ("g", 0));
+    }
+}
@@ -0,0 +1,224 @@
+//! The lambda benchmark with de Bruijn indices instead of named variables.
+//!
+//! `(var i)` refers to the i-th enclosing binder, counting from 0, where
+//! `lam`, `fix` and the body of `let` bind one variable each. Substitution is
+//! explicit: `(subst j v e)` replaces index `j` in `e` with `v` and lowers the
+//! indices above `j`, and `(shift d c e)` adds `d` to the indices of `e` that
+//! are at least `c`. Indices are numbers, so `(+ ?j 1)` on the right-hand
+//! side of a rule gets constant folded into the next index.
+
+use crate::*;
+use egg::{define_language, rewrite as rw, Id};
+use std::cmp::Ordering;
+
+pub fn debruijn_bench() -> Result<Bench<DeBruijn, ConstantFold>, Vec<String>> {
+    let (rules, conditionals) = rules();
+    Ok(Bench {
+        name: "debruijn".into(),
+        rules,
+        bench_pats: parse_patterns("debruijn")?,
+        conditionals,
+        has_data: Some(|data| data.is_some()),
+        // the start programs of the lambda benchmark
+        start_exprs: [
+            // zeroone
+            "(let (lam
+                (if (= (var 0) 0)
+                    0
+                    1))
+                (+ (app (var 0) 0)
+                (app (var 0) 10)))",
+            // compose, repeat, add1
+            "(let (lam (lam (lam (app (var 2)
+                                 (app (var 1) (var 0))))))
+            (let (fix (lam (lam
+                (if (= (var 0) 0)
+                    (lam (var 0))
+                    (app (app (var 3) (var 1))
+                        (app (app (var 2)
+                                (var 1))
+                            (+ (var 0) -1)))))))
+            (let (lam (+ (var 0) 1))
+            (app (app (var 1)
+                    (var 0))
+                2))))",
+            // fib
+            "(let (fix (lam
+                (if (= (var 0) 0)
+                    0
+                (if (= (var 0) 1)
+                    1
+                (+ (app (var 1)
+                        (+ (var 0) -1))
+                    (app (var 1)
+                        (+ (var 0) -2)))))))
+                (app (var 0) 4))",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+    })
+}
+
+define_language! {
+    pub enum DeBruijn {
+        Bool(bool),
+        Num(i32),
+
+        "var" = Var(Id),
+
+        "+" = Add([Id; 2]),
+        "=" = Eq([Id; 2]),
+
+        "app" = App([Id; 2]),
+        "lam" = Lambda(Id),
+        // value, body
+        "let" = Let([Id; 2]),
+        "fix" = Fix(Id),
+
+        "if" = If([Id; 3]),
+
+        // amount, cutoff, term
+        "shift" = Shift([Id; 3]),
+        // index, value, term
+        "subst" = Subst([Id; 3]),
+    }
+}
+
+type EGraph = egg::EGraph<DeBruijn, ConstantFold>;
+
+#[derive(Default, Clone)]
+pub struct ConstantFold;
+impl Analysis<DeBruijn> for ConstantFold {
+    type Data = Option<DeBruijn>;
+
+    fn make(egraph: &EGraph, enode: &DeBruijn) -> Self::Data {
+        let x = |i: &Id| egraph[*i].data.clone();
+        match enode {
+            DeBruijn::Num(_) | DeBruijn::Bool(_) => Some(enode.clone()),
+            DeBruijn::Add([a, b]) => Some(DeBruijn::Num(x(a)?.num()? + x(b)?.num()?)),
+            DeBruijn::Eq([a, b]) => Some(DeBruijn::Bool(x(a)? == x(b)?)),
+            _ => None,
+        }
+    }
+
+    fn merge(&self, to: &mut Self::Data, from: Self::Data) -> Option<Ordering> {
+        if to.is_none() && from.is_some() {
+            *to = from;
+            Some(Ordering::Less)
+        } else {
+            Some(Ordering::Greater)
+        }
+    }
+
+    fn modify(egraph: &mut EGraph, id: Id) {
+        if let Some(c) = egraph[id].data.clone() {
+            let const_id = egraph.add(c);
+            egraph.union(id, const_id);
+        }
+    }
+}
+
+impl DeBruijn {
+    fn num(&self) -> Option<i32> {
+        match self {
+            DeBruijn::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+fn index(egraph: &EGraph, id: Id) -> Option<i32> {
+    egraph[id].data.as_ref()?.num()
+}
+
+fn const_class(egraph: &EGraph, id: Id) -> bool {
+    egraph[id].data.is_some()
+}
+
+/// The indices bound to `a` and `b` are known and compare as `ord`.
+fn index_cmp(
+    a: &str,
+    ord: &'static [Ordering],
+    b: &str,
+) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let a = a.parse().unwrap();
+    let b = b.parse().unwrap();
+    move |egraph, _, subst| match (index(egraph, subst[a]), index(egraph, subst[b])) {
+        (Some(a), Some(b)) => ord.contains(&a.cmp(&b)),
+        _ => false,
+    }
+}
+
+const LESS: &[Ordering] = &[Ordering::Less];
+const GREATER: &[Ordering] = &[Ordering::Greater];
+const AT_LEAST: &[Ordering] = &[Ordering::Greater, Ordering::Equal];
+
+/// The rules, and the searchers of the conditional ones for `--conditional`.
+#[rustfmt::skip]
+fn rules() -> (Vec<Rewrite<DeBruijn, ConstantFold>>, Vec<ConditionalSearcher<DeBruijn, ConstantFold>>) {
+    let mut c = Conditionals::default();
+    let rules = vec![
+        // open term rules
+        rw!("if-true";  "(if  true ?then ?else)" => "?then"),
+        rw!("if-false"; "(if false ?then ?else)" => "?else"),
+        // ?then with ?e substituted for index ?x, without lowering the others:
+        // the shift moves index ?x out of the way of the subst
+        c.add(ConditionalSearcher::new("if-elim", "(if (= (var ?x) ?e) ?then ?else)")
+            .condition(ConditionEqual::parse("(subst (+ ?x 1) ?e (shift 1 ?x ?then))",
+                                             "(subst (+ ?x 1) ?e (shift 1 ?x ?else))")))
+            .rewrite("?else"),
+        rw!("add-comm";  "(+ ?a ?b)"        => "(+ ?b ?a)"),
+        rw!("add-assoc"; "(+ (+ ?a ?b) ?c)" => "(+ ?a (+ ?b ?c))"),
+        rw!("eq-comm";   "(= ?a ?b)"        => "(= ?b ?a)"),
+
+        // binders
+        rw!("fix";  "(fix ?body)"            => "(let (fix ?body) ?body)"),
+        rw!("beta"; "(app (lam ?body) ?e)"   => "(let ?e ?body)"),
+        rw!("let";  "(let ?e ?body)"         => "(subst 0 ?e ?body)"),
+
+        // substitution
+        rw!("subst-app"; "(subst ?j ?v (app ?a ?b))" => "(app (subst ?j ?v ?a) (subst ?j ?v ?b))"),
+        rw!("subst-add"; "(subst ?j ?v (+   ?a ?b))" => "(+   (subst ?j ?v ?a) (subst ?j ?v ?b))"),
+        rw!("subst-eq";  "(subst ?j ?v (=   ?a ?b))" => "(=   (subst ?j ?v ?a) (subst ?j ?v ?b))"),
+        rw!("subst-if";
+            "(subst ?j ?v (if ?cond ?then ?else))" =>
+            "(if (subst ?j ?v ?cond) (subst ?j ?v ?then) (subst ?j ?v ?else))"),
+        rw!("subst-lam"; "(subst ?j ?v (lam ?b))" => "(lam (subst (+ ?j 1) (shift 1 0 ?v) ?b))"),
+        rw!("subst-fix"; "(subst ?j ?v (fix ?b))" => "(fix (subst (+ ?j 1) (shift 1 0 ?v) ?b))"),
+        rw!("subst-let";
+            "(subst ?j ?v (let ?e ?b))" =>
+            "(let (subst ?j ?v ?e) (subst (+ ?j 1) (shift 1 0 ?v) ?b))"),
+        rw!("subst-var-same";  "(subst ?j ?v (var ?j))" => "?v"),
+        c.add(ConditionalSearcher::new("subst-var-below", "(subst ?j ?v (var ?k))")
+            .condition(index_cmp("?k", LESS, "?j")))
+            .rewrite("(var ?k)"),
+        c.add(ConditionalSearcher::new("subst-var-above", "(subst ?j ?v (var ?k))")
+            .condition(index_cmp("?k", GREATER, "?j")))
+            .rewrite("(var (+ ?k -1))"),
+        c.add(ConditionalSearcher::new("subst-const", "(subst ?j ?v ?c)").guard("?c", const_class))
+            .rewrite("?c"),
+
+        // shifting
+        rw!("shift-app"; "(shift ?d ?c (app ?a ?b))" => "(app (shift ?d ?c ?a) (shift ?d ?c ?b))"),
+        rw!("shift-add"; "(shift ?d ?c (+   ?a ?b))" => "(+   (shift ?d ?c ?a) (shift ?d ?c ?b))"),
+        rw!("shift-eq";  "(shift ?d ?c (=   ?a ?b))" => "(=   (shift ?d ?c ?a) (shift ?d ?c ?b))"),
+        rw!("shift-if";
+            "(shift ?d ?c (if ?cond ?then ?else))" =>
+            "(if (shift ?d ?c ?cond) (shift ?d ?c ?then) (shift ?d ?c ?else))"),
+        rw!("shift-lam"; "(shift ?d ?c (lam ?b))" => "(lam (shift ?d (+ ?c 1) ?b))"),
+        rw!("shift-fix"; "(shift ?d ?c (fix ?b))" => "(fix (shift ?d (+ ?c 1) ?b))"),
+        rw!("shift-let";
+            "(shift ?d ?c (let ?e ?b))" =>
+            "(let (shift ?d ?c ?e) (shift ?d (+ ?c 1) ?b))"),
+        c.add(ConditionalSearcher::new("shift-var-below", "(shift ?d ?c (var ?k))")
+            .condition(index_cmp("?k", LESS, "?c")))
+            .rewrite("(var ?k)"),
+        c.add(ConditionalSearcher::new("shift-var-above", "(shift ?d ?c (var ?k))")
+            .condition(index_cmp("?k", AT_LEAST, "?c")))
+            .rewrite("(var (+ ?k ?d))"),
+        c.add(ConditionalSearcher::new("shift-const", "(shift ?d ?c ?n)").guard("?n", const_class))
+            .rewrite("?n"),
+    ];
+    (rules, c.into_searchers())
+}
@@ -0,0 +1,168 @@
+//! The machine and build a run happened on, written next to its results as
+//! `<results>-env.json`, so results from different machines don't get mixed
+//! up. The build half comes from `build.rs`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Environment {
+    pub cpu_model: String,
+    /// Logical cores.
+    pub cores: usize,
+    /// Only compared in GiB, as the same machines can differ by a few MB
+    /// that the firmware or kernel reserve.
+    pub memory_kb: u64,
+    pub kernel: String,
+    /// The cpufreq governor of cpu0, like `performance` or `powersave`.
+    pub governor: String,
+    pub rustc: String,
+    /// The commit of egg from Cargo.lock.
+    pub egg_rev: String,
+    /// `release` or `debug`.
+    pub profile: String,
+    /// The commit of this repository the binary was built from.
+    pub commit: String,
+    /// What was run, not compared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunConfig>,
+}
+
+/// What a run was, recorded next to its results so that it's known even if
+/// the run has none, like a shard that got no searches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunConfig {
+    /// See `Opt::config_hash`.
+    pub config_hash: String,
+    /// The `--shard` of the run, like `1/4`.
+    pub shard: String,
+}
+
+impl Environment {
+    /// The current machine and this binary. What can't be read is `unknown`.
+    pub fn current() -> Self {
+        let read = |path: &str| fs::read_to_string(path).unwrap_or_default();
+        let cpuinfo = read("/proc/cpuinfo");
+        let field = |text: &str, name: &str| {
+            text.lines()
+                .filter_map(|l| l.split_once(':'))
+                .find(|(k, _)| k.trim() == name)
+                .map(|(_, v)| v.trim().to_string())
+        };
+        let or_unknown = |s: Option<String>| {
+            s.filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "unknown".into())
+        };
+        let memory_kb = field(&read("/proc/meminfo"), "MemTotal")
+            .and_then(|m| m.trim_end_matches("kB").trim().parse().ok())
+            .unwrap_or(0);
+        Environment {
+            cpu_model: or_unknown(field(&cpuinfo, "model name")),
+            cores: cpuinfo
+                .lines()
+                .filter_map(|l| l.split_once(':'))
+                .filter(|(k, _)| k.trim() == "processor")
+                .count(),
+            memory_kb,
+            kernel: or_unknown(Some(read("/proc/sys/kernel/osrelease").trim().to_string())),
+            governor: or_unknown(Some(
+                read("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor")
+                    .trim()
+                    .to_string(),
+            )),
+            rustc: env!("EGG_BENCH_RUSTC").into(),
+            egg_rev: env!("EGG_BENCH_EGG_REV").into(),
+            profile: env!("EGG_BENCH_PROFILE").into(),
+            commit: env!("EGG_BENCH_COMMIT").into(),
+            run: None,
+        }
+    }
+
+    /// The fields that differ, with this and the other value.
+    pub fn differences(&self, other: &Environment) -> Vec<(String, String, String)> {
+        let fields = |env: &Environment| match serde_json::to_value(env).unwrap() {
+            serde_json::Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        let gib = |kb: u64| (kb + (1 << 19)) >> 20;
+        let (mine, theirs) = (fields(self), fields(other));
+        mine.iter()
+            .filter(|(k, _)| *k != "run")
+            .filter(|(k, _)| *k != "memory_kb" || gib(self.memory_kb) != gib(other.memory_kb))
+            .filter(|(k, v)| theirs.get(*k) != Some(v))
+            .map(|(k, v)| (k.clone(), v.to_string(), theirs[k].to_string()))
+            .collect()
+    }
+}
+
+/// `out/benchmark-env.json` for `out/benchmark.csv`.
+pub fn path_for(results: &Path) -> PathBuf {
+    crate::sidecar(results, "env.json")
+}
+
+pub fn write(results: &Path, env: &Environment) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(env).unwrap();
+    fs::write(path_for(results), json + "\n")
+}
+
+/// The environment recorded for a results file, `None` for results written
+/// before environments were recorded.
+pub fn read(results: &Path) -> Result<Option<Environment>, String> {
+    let path = path_for(results);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Checks that all the results files come from the same environment, and
+/// returns the problems if they don't. Files without one are skipped with a
+/// warning.
+pub fn check_compatible(files: &[PathBuf]) -> Result<(), Vec<String>> {
+    let mut first: Option<(&PathBuf, Environment)> = None;
+    let mut problems = vec![];
+    for file in files {
+        let env = match read(file) {
+            Ok(Some(env)) => env,
+            Ok(None) => {
+                if files.len() > 1 {
+                    eprintln!(
+                        "warning: {} has no {}, so its environment can't be checked",
+                        file.display(),
+                        path_for(file).display()
+                    );
+                }
+                continue;
+            }
+            Err(e) => {
+                problems.push(e);
+                continue;
+            }
+        };
+        match &first {
+            None => first = Some((file, env)),
+            Some((first_file, first_env)) => {
+                for (field, a, b) in first_env.differences(&env) {
+                    problems.push(format!(
+                        "{} and {} differ in {}: {} vs {}",
+                        first_file.display(),
+                        file.display(),
+                        field,
+                        a,
+                        b
+                    ));
+                }
+            }
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
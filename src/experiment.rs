@@ -0,0 +1,304 @@
+//! The `run` subcommand: runs the experiments described in a TOML file, like
+//! `experiments.toml`, instead of passing the options on the command line.
+//!
+//! Each `[[experiment]]` has a `name` and any of the options of a run, spelled
+//! as in `Opt` with underscores. Options it leaves out have their default.
+//! `pattern` selects patterns like `--pattern`, and `patterns` does so per
+//! bench:
+//!
+//! ```toml
+//! [[experiment]]
+//! name = "short"
+//! benchmarks = ["math", "lambda"]
+//! sizes = [10000, 100000]
+//! timeout = 5
+//! patterns = { math = ["(* ?a (+ ?b ?c))"] }
+//! ```
+
+use crate::patterns::PatternType;
+use crate::{Opt, BENCHES};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct RunOpt {
+    /// The experiment file
+    file: PathBuf,
+    /// Only run these experiments; all of them by default
+    names: Vec<String>,
+    /// Only run the i-th of n parts of the searches of each experiment, as
+    /// with the --shard of a run. Experiments that set a shard must agree.
+    #[structopt(long)]
+    shard: Option<crate::shard::Shard>,
+    /// With --shard, give out single searches, as with the --shard-by-pattern
+    /// of a run
+    #[structopt(long)]
+    shard_by_pattern: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExperimentFile {
+    experiment: Vec<Experiment>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Experiment {
+    name: String,
+    benchmarks: Option<Vec<String>>,
+    sizes: Option<Vec<usize>>,
+    params: Option<Vec<usize>>,
+    strategy: Option<String>,
+    samples: Option<usize>,
+    timeout: Option<f64>,
+    memory_limit: Option<u64>,
+    category: Option<Vec<PatternType>>,
+    conditional: Option<bool>,
+    pushdown: Option<bool>,
+    fpcore: Option<Vec<String>>,
+    pattern: Option<Vec<String>>,
+    patterns: Option<BTreeMap<String, Vec<String>>>,
+    shard: Option<String>,
+    shard_by_pattern: Option<bool>,
+    /// `out/<name>.csv` by default.
+    filename: Option<String>,
+    format: Option<crate::ResultsFormat>,
+    trace: Option<String>,
+    verbose: Option<bool>,
+}
+
+impl Experiment {
+    /// The options of a run with the defaults overridden by the experiment.
+    fn opt(self) -> Result<Opt, String> {
+        let mut opt = Opt::from_iter(&["egg-bench"]);
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = self.$field {
+                    opt.$field = value;
+                })*
+            };
+        }
+        set!(
+            benchmarks,
+            sizes,
+            params,
+            strategy,
+            samples,
+            timeout,
+            category,
+            conditional,
+            pushdown,
+            fpcore,
+            shard_by_pattern,
+            format,
+            verbose
+        );
+        let name = &self.name;
+        let filters = |texts: Vec<String>| {
+            texts
+                .iter()
+                .map(|t| t.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("experiment {}: {}", name, e))
+        };
+        opt.pattern = filters(self.pattern.unwrap_or_default())?;
+        opt.patterns = self
+            .patterns
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(bench, texts)| Ok((bench, filters(texts)?)))
+            .collect::<Result<_, String>>()?;
+        if let Some(shard) = &self.shard {
+            opt.shard = shard
+                .parse()
+                .map_err(|e| format!("experiment {}: {}", name, e))?;
+        }
+        opt.memory_limit = self.memory_limit;
+        opt.trace = self.trace;
+        opt.filename = match self.filename {
+            Some(filename) => filename,
+            None => format!("out/{}.{}", self.name, opt.format),
+        };
+
+        // report and compare-runs read results by their extension
+        let extension = Path::new(&opt.filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| ["csv", "jsonl"].contains(e));
+        let problem = if let Some(b) = opt.benchmarks.iter().find(|b| !BENCHES.contains(&&b[..])) {
+            Some(format!("unknown bench {}", b))
+        } else if let Some(b) = opt.patterns.keys().find(|b| !opt.benchmarks.contains(b)) {
+            Some(format!("patterns are given for {}, which isn't run", b))
+        } else if !["all", "gj", "em"].contains(&opt.strategy.as_str()) {
+            Some(format!(
+                "strategy should be one of all, gj, or em, got {}",
+                opt.strategy
+            ))
+        } else if opt.memory_limit == Some(0) {
+            Some("memory_limit should be at least 1 MB".to_string())
+        } else if opt.memory_limit.is_some() && crate::status::rss_kb().is_none() {
+            Some("memory_limit needs /proc/self/status, which this system doesn't have".into())
+        } else if let Some(ext) = extension.filter(|e| *e != opt.format.to_string()) {
+            Some(format!(
+                "filename {} ends in .{}, but the format is {}",
+                opt.filename, ext, opt.format
+            ))
+        } else {
+            None
+        };
+        match problem {
+            Some(p) => Err(format!("experiment {}: {}", self.name, p)),
+            None => Ok(opt),
+        }
+    }
+}
+
+/// Runs the selected experiments one after the other. The whole file is
+/// checked first, so a typo doesn't stop it hours in.
+pub fn run(run_opt: &RunOpt) {
+    let text = std::fs::read_to_string(&run_opt.file)
+        .unwrap_or_else(|e| fail(format!("cannot read {}: {}", run_opt.file.display(), e)));
+    let file: ExperimentFile = toml::from_str(&text)
+        .unwrap_or_else(|e| fail(format!("{}: {}", run_opt.file.display(), e)));
+    for name in &run_opt.names {
+        if !file.experiment.iter().any(|e| &e.name == name) {
+            fail(format!(
+                "{} has no experiment {}",
+                run_opt.file.display(),
+                name
+            ));
+        }
+    }
+
+    let mut opts = vec![];
+    let mut outputs = HashMap::new();
+    for experiment in file.experiment {
+        let name = experiment.name.clone();
+        let sets_shard = experiment.shard.is_some();
+        let sets_split = experiment.shard_by_pattern.is_some();
+        let mut opt = experiment.opt().unwrap_or_else(|e| fail(e));
+        check_outputs(&mut outputs, &name, &opt).unwrap_or_else(|e| fail(e));
+        if !(run_opt.names.is_empty() || run_opt.names.contains(&name)) {
+            continue;
+        }
+        if let Some(shard) = run_opt.shard {
+            if sets_shard && shard != opt.shard {
+                fail(format!(
+                    "experiment {} is shard {}, but --shard is {}",
+                    name, opt.shard, shard
+                ));
+            }
+            opt.shard = shard;
+        }
+        if run_opt.shard_by_pattern {
+            if sets_split && !opt.shard_by_pattern {
+                fail(format!(
+                    "experiment {} sets shard_by_pattern = false, but --shard-by-pattern is given",
+                    name
+                ));
+            }
+            opt.shard_by_pattern = true;
+        }
+        opt.import_fpcore()
+            .unwrap_or_else(|e| fail(format!("experiment {}: {}", name, e)));
+        if let Err(problems) = opt.check_filters() {
+            fail(format!("experiment {}: {}", name, problems.join("; ")));
+        }
+        opts.push((name, opt));
+    }
+    for (name, opt) in &opts {
+        eprintln!(
+            "Running experiment {} into {} (config {})",
+            name,
+            opt.filename,
+            opt.config_hash()
+        );
+        crate::run(opt);
+    }
+}
+
+/// Records the files the experiment writes in `outputs`, and fails if an
+/// earlier experiment writes one of them too, as it would be overwritten.
+/// Results with the same name but another extension share the stats file.
+fn check_outputs(
+    outputs: &mut HashMap<PathBuf, String>,
+    name: &str,
+    opt: &Opt,
+) -> Result<(), String> {
+    let results = PathBuf::from(&opt.filename);
+    let stats = crate::stats::path_for(&results);
+    for path in vec![results, stats] {
+        if let Some(other) = outputs.insert(path.clone(), name.to_string()) {
+            return Err(format!(
+                "experiments {} and {} both write {}",
+                other,
+                name,
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn experiments(text: &str) -> Vec<Experiment> {
+        toml::from_str::<ExperimentFile>(text).unwrap().experiment
+    }
+
+    #[test]
+    fn bad_entries() {
+        let problem = |entry: &str| {
+            let text = format!("[[experiment]]\nname = \"e\"\n{}\n", entry);
+            experiments(&text).pop().unwrap().opt().unwrap_err()
+        };
+        assert_eq!(
+            problem("memory_limit = 0"),
+            "experiment e: memory_limit should be at least 1 MB"
+        );
+        assert_eq!(
+            problem("format = \"jsonl\"\nfilename = \"out/e.csv\""),
+            "experiment e: filename out/e.csv ends in .csv, but the format is jsonl"
+        );
+        assert!(problem("shard = \"2/2\"").starts_with("experiment e: shard should be"));
+        assert!(toml::from_str::<ExperimentFile>(
+            "[[experiment]]\nname = \"e\"\nformat = \"xml\"\n"
+        )
+        .is_err());
+
+        let text = "[[experiment]]\nname = \"e\"\nformat = \"jsonl\"\nshard = \"1/4\"\n";
+        let opt = experiments(text).pop().unwrap().opt().unwrap();
+        assert_eq!(opt.filename, "out/e.jsonl");
+        assert_eq!(opt.shard.to_string(), "1/4");
+    }
+
+    #[test]
+    fn same_outputs() {
+        let text = "[[experiment]]\nname = \"a\"\n\
+                    [[experiment]]\nname = \"b\"\nfilename = \"out/c.csv\"\n\
+                    [[experiment]]\nname = \"c\"\nformat = \"jsonl\"\n";
+        let mut outputs = HashMap::new();
+        let mut check = |e: Experiment| {
+            let name = e.name.clone();
+            check_outputs(&mut outputs, &name, &e.opt().unwrap())
+        };
+        let mut entries = experiments(text).into_iter();
+        assert_eq!(check(entries.next().unwrap()), Ok(()));
+        assert_eq!(check(entries.next().unwrap()), Ok(()));
+        // out/c.jsonl has the stats file of out/c.csv
+        assert_eq!(
+            check(entries.next().unwrap()),
+            Err("experiments b and c both write out/c-stats.csv".to_string())
+        );
+    }
+}
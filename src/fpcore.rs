@@ -0,0 +1,281 @@
+//! Importer for FPCore files, the benchmark format used by Herbie.
+//!
+//! Each `(FPCore (args...) :prop value ... body)` is translated into a
+//! `Math` expression. `let` and `let*` bindings are inlined, and cores that
+//! use operators `Math` has no counterpart for (comparisons, `if`, `while`,
+//! most of libm) are skipped.
+
+use crate::math::Math;
+use egg::RecExpr;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            '"' => {
+                // strings only show up as property values, keep them whole
+                current.push(c);
+                for c in chars.by_ref() {
+                    current.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '(' | ')' | '[' | ']' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_sexps(input: &str) -> Result<Vec<Sexp>, String> {
+    let mut stack: Vec<Vec<Sexp>> = vec![vec![]];
+    for token in tokenize(input) {
+        match token.as_str() {
+            "(" | "[" => stack.push(vec![]),
+            ")" | "]" => {
+                let list = stack.pop().unwrap();
+                stack
+                    .last_mut()
+                    .ok_or_else(|| "unbalanced closing paren".to_string())?
+                    .push(Sexp::List(list));
+            }
+            _ => stack.last_mut().unwrap().push(Sexp::Atom(token)),
+        }
+    }
+    if stack.len() != 1 {
+        return Err("unbalanced opening paren".into());
+    }
+    Ok(stack.pop().unwrap())
+}
+
+fn translate_number(s: &str) -> Option<String> {
+    if let Some((num, den)) = s.split_once('/') {
+        num.parse::<f64>().ok()?;
+        den.parse::<f64>().ok()?;
+        return Some(format!("(/ {} {})", num, den));
+    }
+    let n: f64 = s.parse().ok()?;
+    if n.is_finite() {
+        Some(n.to_string())
+    } else {
+        None
+    }
+}
+
+fn translate(sexp: &Sexp, env: &HashMap<String, String>) -> Result<String, String> {
+    let list = match sexp {
+        Sexp::Atom(a) => {
+            if let Some(bound) = env.get(a) {
+                return Ok(bound.clone());
+            }
+            if let Some(n) = translate_number(a) {
+                return Ok(n);
+            }
+            return match a.as_str() {
+                "PI" => Ok("pi".into()),
+                "E" => Ok("e".into()),
+                "INFINITY" | "NAN" | "TRUE" | "FALSE" => Err(format!("unsupported constant {}", a)),
+                _ => Ok(a.clone()),
+            };
+        }
+        Sexp::List(list) => list,
+    };
+
+    let op = match list.first() {
+        Some(Sexp::Atom(op)) => op.as_str(),
+        _ => return Err("expected an operator".into()),
+    };
+    let args = &list[1..];
+
+    if op == "let" || op == "let*" {
+        let (bindings, body) = match args {
+            [Sexp::List(bindings), body] => (bindings, body),
+            _ => return Err(format!("malformed {}", op)),
+        };
+        let mut inner = env.clone();
+        for binding in bindings {
+            match binding {
+                Sexp::List(b) => match b.as_slice() {
+                    [Sexp::Atom(name), value] => {
+                        // let evaluates every binding in the outer scope
+                        let scope = if op == "let*" { &inner } else { env };
+                        let value = translate(value, scope)?;
+                        inner.insert(name.clone(), value);
+                    }
+                    _ => return Err(format!("malformed {} binding", op)),
+                },
+                _ => return Err(format!("malformed {} binding", op)),
+            }
+        }
+        return translate(body, &inner);
+    }
+
+    let args = args
+        .iter()
+        .map(|a| translate(a, env))
+        .collect::<Result<Vec<_>, _>>()?;
+    let math_op = match (op, args.len()) {
+        ("-", 1) => return Ok(format!("(* -1 {})", args[0])),
+        ("+", 2) | ("-", 2) | ("*", 2) | ("/", 2) | ("pow", 2) => op,
+        ("sqrt", 1) | ("sin", 1) | ("cos", 1) | ("tan", 1) | ("atan", 1) => op,
+        ("exp", 1) | ("cbrt", 1) | ("fabs", 1) => op,
+        ("log", 1) => "ln",
+        _ => return Err(format!("unsupported operator {}/{}", op, args.len())),
+    };
+    Ok(format!("({} {})", math_op, args.join(" ")))
+}
+
+fn translate_core(core: &Sexp) -> Result<String, String> {
+    let list = match core {
+        Sexp::List(list) => list,
+        _ => return Err("expected (FPCore ...)".into()),
+    };
+    match list.first() {
+        Some(Sexp::Atom(head)) if head == "FPCore" => (),
+        _ => return Err("expected (FPCore ...)".into()),
+    }
+    // FPCore 2.0 allows an identifier before the argument list
+    let rest = match list.get(1) {
+        Some(Sexp::Atom(_)) => &list[2..],
+        _ => &list[1..],
+    };
+    // the body is whatever is left after the arguments and :property pairs
+    let mut i = 1;
+    while i + 1 < rest.len() {
+        match &rest[i] {
+            Sexp::Atom(a) if a.starts_with(':') => i += 2,
+            _ => break,
+        }
+    }
+    if i + 1 != rest.len() {
+        return Err("expected a single body".into());
+    }
+    let expr = translate(&rest[i], &HashMap::new())?;
+    expr.parse::<RecExpr<Math>>().map_err(|e| e.to_string())?;
+    Ok(expr)
+}
+
+/// Translates every core in an FPCore source. Cores that don't fit in `Math`
+/// come back as an `Err` with the reason.
+pub fn import_str(input: &str) -> Result<Vec<Result<String, String>>, String> {
+    Ok(parse_sexps(input)?.iter().map(translate_core).collect())
+}
+
+/// Imports a single `.fpcore` file, or every `.fpcore` file in a directory.
+/// Fails if one can't be read or parsed.
+pub fn import(path: &str, verbose: bool) -> Result<Vec<String>, String> {
+    let path = Path::new(path);
+    let error = |file: &Path, e: &dyn std::fmt::Display| format!("{}: {}", file.display(), e);
+    let mut files = vec![];
+    if path.is_dir() {
+        for entry in fs::read_dir(path).map_err(|e| error(path, &e))? {
+            let file = entry.map_err(|e| error(path, &e))?.path();
+            if file.extension() == Some("fpcore".as_ref()) {
+                files.push(file);
+            }
+        }
+        files.sort();
+    } else {
+        files.push(path.to_path_buf());
+    }
+
+    let mut exprs = vec![];
+    for file in files {
+        let input = fs::read_to_string(&file).map_err(|e| error(&file, &e))?;
+        let cores = import_str(&input).map_err(|e| error(&file, &e))?;
+        let mut skipped = 0;
+        for (i, core) in cores.into_iter().enumerate() {
+            match core {
+                Ok(expr) => exprs.push(expr),
+                Err(reason) => {
+                    skipped += 1;
+                    if verbose {
+                        eprintln!("{}: skipping core #{}: {}", file.display(), i, reason);
+                    }
+                }
+            }
+        }
+        eprintln!("{}: skipped {} cores", file.display(), skipped);
+    }
+    Ok(exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_cores() {
+        let input = r#"
+            ; a comment (with parens)
+            (FPCore (x) :name "1 - cos(x)" :pre (> x 0) (- 1 (cos x)))
+            (FPCore named (x y)
+              (let* ([a (* x x)] [b (+ a y)]) (/ b (sqrt PI))))
+            (FPCore (x) (let ([x 2] [y x]) (+ x y)))
+            (FPCore (x) (log (- x)))
+            (FPCore (x) (if (< x 0) 1 x))
+            (FPCore (x) (+ x INFINITY))
+        "#;
+        let cores = import_str(input).unwrap();
+        assert_eq!(
+            cores,
+            vec![
+                Ok("(- 1 (cos x))".to_string()),
+                Ok("(/ (+ (* x x) y) (sqrt pi))".to_string()),
+                // let binds in the outer scope, where x is the argument
+                Ok("(+ 2 x)".to_string()),
+                Ok("(ln (* -1 x))".to_string()),
+                Err("unsupported operator </2".to_string()),
+                Err("unsupported constant INFINITY".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_malformed() {
+        assert!(import_str("(FPCore (x) (+ x 1)").is_err());
+        assert!(import_str("(FPCore (x) x))").is_err());
+        assert_eq!(
+            import_str("(FPCore (x) :name \"two bodies\" x x)").unwrap(),
+            vec![Err("expected a single body".to_string())]
+        );
+        assert_eq!(
+            import_str("(Core (x) x)").unwrap(),
+            vec![Err("expected (FPCore ...)".to_string())]
+        );
+    }
+}
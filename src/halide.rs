@@ -0,0 +1,378 @@
+use crate::*;
+use egg::{define_language, rewrite as rw, Id, Symbol};
+use std::cmp::Ordering;
+
+pub fn halide_bench() -> Result<Bench<Halide, ConstantInterval>, Vec<String>> {
+    let (rules, conditionals) = rules();
+    Ok(Bench {
+        name: "halide".into(),
+        start_exprs: [
+            // extent of the last tile of a split loop
+            "(- (min (+ (* xo 16) 16) w) (min (* xo 16) w))",
+            // vectorized index arithmetic
+            "(/ (+ (* x 4) (+ y 3)) 4)",
+            "(% (+ (* x 8) 5) 4)",
+            "(+ (* (/ x 8) 8) (% x 8))",
+            // clamped accesses
+            "(min (max (% i 16) 0) 15)",
+            "(max (min (+ x 3) (+ y 5)) (+ x 1))",
+            "(select (< (+ x 1) (+ y 1)) (+ x 1) (+ y 1))",
+            // boundary condition of a stencil
+            "(select (< (+ (* x 2) 1) (* x 2)) (min (+ x 2) (- w 1)) (max (- x 1) 0))",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rules,
+        bench_pats: parse_patterns("halide")?,
+        conditionals,
+        has_data: Some(|data| data.constant.is_some() || data.lo.is_some() || data.hi.is_some()),
+    })
+}
+
+define_language! {
+    pub enum Halide {
+        "+" = Add([Id; 2]),
+        "-" = Sub([Id; 2]),
+        "*" = Mul([Id; 2]),
+        "/" = Div([Id; 2]),
+        "%" = Mod([Id; 2]),
+        "min" = Min([Id; 2]),
+        "max" = Max([Id; 2]),
+        "select" = Select([Id; 3]),
+
+        "<" = Lt([Id; 2]),
+        "<=" = Le([Id; 2]),
+        "==" = Eq([Id; 2]),
+        "!" = Not(Id),
+        "&&" = And([Id; 2]),
+        "||" = Or([Id; 2]),
+
+        Bool(bool),
+        Num(i64),
+        Symbol(Symbol),
+    }
+}
+
+impl Halide {
+    fn num(&self) -> Option<i64> {
+        match self {
+            Halide::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn bool(&self) -> Option<bool> {
+        match self {
+            Halide::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+type EGraph = egg::EGraph<Halide, ConstantInterval>;
+
+#[derive(Default, Clone)]
+pub struct ConstantInterval;
+
+/// The constant an e-class folds to, and bounds on its integer value.
+/// A missing bound is unbounded in that direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    constant: Option<Halide>,
+    lo: Option<i64>,
+    hi: Option<i64>,
+}
+
+// Halide defines division and modulo by zero as zero, and uses Euclidean
+// division so that the remainder is never negative: the quotient rounds
+// towards negative infinity for positive divisors and towards positive
+// infinity for negative ones, e.g. 7 / -2 is -3 and 7 % -2 is 1.
+fn div(a: i64, b: i64) -> Option<i64> {
+    if b == 0 {
+        Some(0)
+    } else {
+        a.checked_div_euclid(b)
+    }
+}
+
+fn rem(a: i64, b: i64) -> Option<i64> {
+    if b == 0 {
+        Some(0)
+    } else {
+        a.checked_rem_euclid(b)
+    }
+}
+
+fn eval(egraph: &EGraph, enode: &Halide) -> Option<Halide> {
+    let n = |i: &Id| egraph[*i].data.constant.as_ref()?.num();
+    let b = |i: &Id| egraph[*i].data.constant.as_ref()?.bool();
+    Some(match enode {
+        Halide::Num(_) | Halide::Bool(_) => enode.clone(),
+        Halide::Add([x, y]) => Halide::Num(n(x)?.checked_add(n(y)?)?),
+        Halide::Sub([x, y]) => Halide::Num(n(x)?.checked_sub(n(y)?)?),
+        Halide::Mul([x, y]) => Halide::Num(n(x)?.checked_mul(n(y)?)?),
+        Halide::Div([x, y]) => Halide::Num(div(n(x)?, n(y)?)?),
+        Halide::Mod([x, y]) => Halide::Num(rem(n(x)?, n(y)?)?),
+        Halide::Min([x, y]) => Halide::Num(n(x)?.min(n(y)?)),
+        Halide::Max([x, y]) => Halide::Num(n(x)?.max(n(y)?)),
+        Halide::Lt([x, y]) => Halide::Bool(n(x)? < n(y)?),
+        Halide::Le([x, y]) => Halide::Bool(n(x)? <= n(y)?),
+        Halide::Eq([x, y]) => Halide::Bool(n(x)? == n(y)?),
+        Halide::Not(x) => Halide::Bool(!b(x)?),
+        Halide::And([x, y]) => Halide::Bool(b(x)? && b(y)?),
+        Halide::Or([x, y]) => Halide::Bool(b(x)? || b(y)?),
+        _ => return None,
+    })
+}
+
+fn bounds(egraph: &EGraph, enode: &Halide) -> (Option<i64>, Option<i64>) {
+    let lo = |i: &Id| egraph[*i].data.lo;
+    let hi = |i: &Id| egraph[*i].data.hi;
+    let both = |f: fn(i64, i64) -> Option<i64>, a: Option<i64>, b: Option<i64>| f(a?, b?);
+    let positive_const = |i: &Id| {
+        let c = egraph[*i].data.constant.as_ref()?.num()?;
+        if c > 0 {
+            Some(c)
+        } else {
+            None
+        }
+    };
+    match enode {
+        Halide::Num(c) => (Some(*c), Some(*c)),
+        Halide::Add([x, y]) => (
+            both(i64::checked_add, lo(x), lo(y)),
+            both(i64::checked_add, hi(x), hi(y)),
+        ),
+        Halide::Sub([x, y]) => (
+            both(i64::checked_sub, lo(x), hi(y)),
+            both(i64::checked_sub, hi(x), lo(y)),
+        ),
+        Halide::Min([x, y]) => (both(|a, b| Some(a.min(b)), lo(x), lo(y)), {
+            // either side being bounded above bounds the min
+            match (hi(x), hi(y)) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }),
+        Halide::Max([x, y]) => (
+            match (lo(x), lo(y)) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+            both(|a, b| Some(a.max(b)), hi(x), hi(y)),
+        ),
+        Halide::Select([_, t, e]) => (
+            both(|a, b| Some(a.min(b)), lo(t), lo(e)),
+            both(|a, b| Some(a.max(b)), hi(t), hi(e)),
+        ),
+        Halide::Mod([_, c]) => match positive_const(c) {
+            Some(c) => (Some(0), Some(c - 1)),
+            None => (None, None),
+        },
+        Halide::Div([x, c]) => match positive_const(c) {
+            Some(c) => (lo(x).and_then(|l| div(l, c)), hi(x).and_then(|h| div(h, c))),
+            None => (None, None),
+        },
+        Halide::Mul([x, c]) | Halide::Mul([c, x]) if positive_const(c).is_some() => {
+            let c = positive_const(c).unwrap();
+            (
+                lo(x).and_then(|l| l.checked_mul(c)),
+                hi(x).and_then(|h| h.checked_mul(c)),
+            )
+        }
+        _ => (None, None),
+    }
+}
+
+impl Analysis<Halide> for ConstantInterval {
+    type Data = Data;
+
+    fn make(egraph: &EGraph, enode: &Halide) -> Data {
+        let constant = eval(egraph, enode);
+        let (lo, hi) = match constant.as_ref().and_then(Halide::num) {
+            Some(c) => (Some(c), Some(c)),
+            None => bounds(egraph, enode),
+        };
+        Data { constant, lo, hi }
+    }
+
+    fn merge(&self, to: &mut Data, from: Data) -> Option<Ordering> {
+        let before = to.clone();
+        if to.constant.is_none() {
+            to.constant = from.constant.clone();
+        }
+        // both bounds hold for the merged class, so keep the tighter ones
+        to.lo = match (to.lo, from.lo) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        to.hi = match (to.hi, from.hi) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        match (*to == before, *to == from) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+
+    fn modify(egraph: &mut EGraph, id: Id) {
+        if let Some(c) = egraph[id].data.constant.clone() {
+            let const_id = egraph.add(c);
+            egraph.union(id, const_id);
+        }
+    }
+}
+
+fn var(s: &str) -> Var {
+    s.parse().unwrap()
+}
+
+fn num(egraph: &EGraph, id: Id) -> Option<i64> {
+    egraph[id].data.constant.as_ref()?.num()
+}
+
+fn const_class(egraph: &EGraph, id: Id) -> bool {
+    num(egraph, id).is_some()
+}
+
+fn positive_class(egraph: &EGraph, id: Id) -> bool {
+    matches!(num(egraph, id), Some(c) if c > 0)
+}
+
+fn negative_class(egraph: &EGraph, id: Id) -> bool {
+    matches!(num(egraph, id), Some(c) if c < 0)
+}
+
+/// `d` is a non-zero constant that divides the constant `c`.
+fn divides(d: &str, c: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let d = var(d);
+    let c = var(c);
+    move |egraph, _, subst| match (num(egraph, subst[d]), num(egraph, subst[c])) {
+        (Some(d), Some(c)) => c.checked_rem(d) == Some(0),
+        _ => false,
+    }
+}
+
+/// The intervals prove that `x <= y`.
+fn can_prove_le(x: &str, y: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let x = var(x);
+    let y = var(y);
+    move |egraph, _, subst| match (egraph[subst[x]].data.hi, egraph[subst[y]].data.lo) {
+        (Some(hi), Some(lo)) => hi <= lo,
+        _ => false,
+    }
+}
+
+/// The intervals prove that `0 <= x < c`.
+fn in_mod_range(x: &str, c: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let x = var(x);
+    let c = var(c);
+    move |egraph, _, subst| {
+        let data = &egraph[subst[x]].data;
+        match (data.lo, data.hi, num(egraph, subst[c])) {
+            (Some(lo), Some(hi), Some(c)) => 0 <= lo && hi < c,
+            _ => false,
+        }
+    }
+}
+
+/// The rules, and the searchers of the conditional ones for `--conditional`.
+#[rustfmt::skip]
+pub fn rules() -> (Vec<Rewrite<Halide, ConstantInterval>>, Vec<ConditionalSearcher<Halide, ConstantInterval>>) {
+    let mut c = Conditionals::default();
+    let rules = vec![
+        rw!("add-comm";  "(+ ?x ?y)"        => "(+ ?y ?x)"),
+        rw!("mul-comm";  "(* ?x ?y)"        => "(* ?y ?x)"),
+        rw!("min-comm";  "(min ?x ?y)"      => "(min ?y ?x)"),
+        rw!("max-comm";  "(max ?x ?y)"      => "(max ?y ?x)"),
+        rw!("add-assoc"; "(+ ?x (+ ?y ?z))" => "(+ (+ ?x ?y) ?z)"),
+        rw!("mul-assoc"; "(* ?x (* ?y ?z))" => "(* (* ?x ?y) ?z)"),
+
+        rw!("sub-canon"; "(- ?x ?y)" => "(+ ?x (* -1 ?y))"),
+        rw!("add-zero";  "(+ ?x 0)"  => "?x"),
+        rw!("mul-one";   "(* ?x 1)"  => "?x"),
+        rw!("mul-zero";  "(* ?x 0)"  => "0"),
+        rw!("sub-self";  "(- ?x ?x)" => "0"),
+        rw!("distribute"; "(* (+ ?x ?y) ?z)" => "(+ (* ?x ?z) (* ?y ?z))"),
+        rw!("factor";     "(+ (* ?x ?z) (* ?y ?z))" => "(* (+ ?x ?y) ?z)"),
+
+        rw!("min-self"; "(min ?x ?x)" => "?x"),
+        rw!("max-self"; "(max ?x ?x)" => "?x"),
+        rw!("min-max";  "(min (max ?x ?y) ?x)" => "?x"),
+        rw!("max-min";  "(max (min ?x ?y) ?x)" => "?x"),
+        c.add(ConditionalSearcher::new("min-le", "(min ?x ?y)").condition(can_prove_le("?x", "?y")))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("max-le", "(max ?x ?y)").condition(can_prove_le("?x", "?y")))
+            .rewrite("?y"),
+
+        rw!("min-add-common"; "(min (+ ?x ?y) (+ ?x ?z))" => "(+ ?x (min ?y ?z))"),
+        rw!("max-add-common"; "(max (+ ?x ?y) (+ ?x ?z))" => "(+ ?x (max ?y ?z))"),
+        c.add(ConditionalSearcher::new("min-add-const", "(min (+ ?x ?c0) ?x)")
+            .guard("?c0", const_class))
+            .rewrite("(+ ?x (min ?c0 0))"),
+        c.add(ConditionalSearcher::new("max-add-const", "(max (+ ?x ?c0) ?x)")
+            .guard("?c0", const_class))
+            .rewrite("(+ ?x (max ?c0 0))"),
+        c.add(ConditionalSearcher::new("min-add-consts", "(min (+ ?x ?c0) (+ ?y ?c1))")
+            .guard("?c0", const_class).guard("?c1", const_class))
+            .rewrite("(+ (min ?x (+ ?y (- ?c1 ?c0))) ?c0)"),
+        c.add(ConditionalSearcher::new("max-add-consts", "(max (+ ?x ?c0) (+ ?y ?c1))")
+            .guard("?c0", const_class).guard("?c1", const_class))
+            .rewrite("(+ (max ?x (+ ?y (- ?c1 ?c0))) ?c0)"),
+        c.add(ConditionalSearcher::new("min-min-const", "(min (min ?x ?c0) ?c1)")
+            .guard("?c0", const_class).guard("?c1", const_class))
+            .rewrite("(min ?x (min ?c0 ?c1))"),
+        c.add(ConditionalSearcher::new("max-max-const", "(max (max ?x ?c0) ?c1)")
+            .guard("?c0", const_class).guard("?c1", const_class))
+            .rewrite("(max ?x (max ?c0 ?c1))"),
+        c.add(ConditionalSearcher::new("min-mul-pos", "(min (* ?x ?c) (* ?y ?c))")
+            .guard("?c", positive_class))
+            .rewrite("(* (min ?x ?y) ?c)"),
+        c.add(ConditionalSearcher::new("min-mul-neg", "(min (* ?x ?c) (* ?y ?c))")
+            .guard("?c", negative_class))
+            .rewrite("(* (max ?x ?y) ?c)"),
+        c.add(ConditionalSearcher::new("max-mul-pos", "(max (* ?x ?c) (* ?y ?c))")
+            .guard("?c", positive_class))
+            .rewrite("(* (max ?x ?y) ?c)"),
+        c.add(ConditionalSearcher::new("max-mul-neg", "(max (* ?x ?c) (* ?y ?c))")
+            .guard("?c", negative_class))
+            .rewrite("(* (min ?x ?y) ?c)"),
+
+        rw!("select-true";  "(select true ?x ?y)"  => "?x"),
+        rw!("select-false"; "(select false ?x ?y)" => "?y"),
+        rw!("select-same";  "(select ?c ?x ?x)"    => "?x"),
+        rw!("select-min";   "(select (< ?x ?y) ?x ?y)" => "(min ?x ?y)"),
+        rw!("select-max";   "(select (< ?x ?y) ?y ?x)" => "(max ?x ?y)"),
+        rw!("select-not";   "(select (! ?c) ?x ?y)"    => "(select ?c ?y ?x)"),
+
+        rw!("lt-le";      "(< ?x ?y)" => "(! (<= ?y ?x))"),
+        rw!("lt-add";     "(< (+ ?x ?z) (+ ?y ?z))" => "(< ?x ?y)"),
+        c.add(ConditionalSearcher::new("lt-prove", "(< ?x ?y)").condition(can_prove_le("?y", "?x")))
+            .rewrite("false"),
+        c.add(ConditionalSearcher::new("le-prove", "(<= ?x ?y)").condition(can_prove_le("?x", "?y")))
+            .rewrite("true"),
+
+        c.add(ConditionalSearcher::new("div-mul", "(/ (* ?x ?c0) ?c1)")
+            .condition(divides("?c1", "?c0")))
+            .rewrite("(* ?x (/ ?c0 ?c1))"),
+        c.add(ConditionalSearcher::new("div-mul-add", "(/ (+ (* ?x ?c0) ?y) ?c1)")
+            .condition(divides("?c1", "?c0")))
+            .rewrite("(+ (* ?x (/ ?c0 ?c1)) (/ ?y ?c1))"),
+        c.add(ConditionalSearcher::new("mod-mul", "(% (* ?x ?c0) ?c1)")
+            .condition(divides("?c1", "?c0")))
+            .rewrite("0"),
+        c.add(ConditionalSearcher::new("mod-mul-add", "(% (+ (* ?x ?c0) ?y) ?c1)")
+            .condition(divides("?c1", "?c0")))
+            .rewrite("(% ?y ?c1)"),
+        c.add(ConditionalSearcher::new("mod-range", "(% ?x ?c)").condition(in_mod_range("?x", "?c")))
+            .rewrite("?x"),
+        c.add(ConditionalSearcher::new("div-mod", "(+ (* (/ ?x ?c) ?c) (% ?x ?c))")
+            .guard("?c", positive_class))
+            .rewrite("?x"),
+    ];
+    (rules, c.into_searchers())
+}
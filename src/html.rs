@@ -0,0 +1,685 @@
+//! The HTML version of the report: one file with the table, the speedup
+//! chart of every bench and e-graph, sortable tables of the patterns, and a
+//! curve of time against e-graph size for every pattern. The charts are
+//! inline SVG and the sorting is a few lines of inline JavaScript, so the
+//! file works offline.
+
+use crate::patterns::fnv1a;
+use crate::report::{fastest, fmt_x, ResultRow, Runs, Summary};
+use crate::status::Status;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: right; }
+td.pattern { text-align: left; font-family: monospace; }
+th { background: #eee; cursor: pointer; }
+.timeout { color: #c00; font-weight: bold; }
+.failed { color: #fff; background: #c00; }
+svg text { font-family: monospace; font-size: 11px; }
+:target { outline: 2px solid #36c; }
+";
+
+/// Sorts a table by the clicked column, by the `data-v` number of its cells
+/// if they have one and by their text otherwise. A second click reverses it.
+const SCRIPT: &str = "
+document.querySelectorAll('table.sortable th').forEach(function (th, i) {
+  th.addEventListener('click', function () {
+    var body = th.closest('table').tBodies[0];
+    var asc = th.dataset.asc !== '1';
+    th.dataset.asc = asc ? '1' : '0';
+    var key = function (tr) {
+      var td = tr.cells[i];
+      return td.dataset.v !== undefined ? parseFloat(td.dataset.v) : td.textContent;
+    };
+    Array.from(body.rows)
+      .sort(function (a, b) {
+        var x = key(a), y = key(b);
+        return (x < y ? -1 : x > y ? 1 : 0) * (asc ? 1 : -1);
+      })
+      .forEach(function (tr) { body.appendChild(tr); });
+  });
+});
+";
+
+/// The fastest sample of a strategy on one e-graph.
+#[derive(Debug, Clone, Copy)]
+struct Time {
+    micros: f64,
+    timeout: bool,
+}
+
+/// The timings of one pattern on one e-graph.
+#[derive(Debug, Clone)]
+struct Point {
+    size: usize,
+    em: Option<Time>,
+    gj0: Option<Time>,
+    gj1: Option<Time>,
+    /// How EM, GJ0 and GJ1 failed, if none of their samples was measured.
+    failed: [Option<(Status, String)>; 3],
+    result_size: String,
+}
+
+impl Point {
+    fn of(size: usize, runs: &Runs, timeout: Option<f64>) -> Self {
+        let measured = |algo: &str, repeat: usize| -> Vec<&ResultRow> {
+            let rows = runs.get(&(algo, repeat)).map_or(&[][..], |rows| &rows[..]);
+            rows.iter()
+                .copied()
+                .filter(|r| r.status().is_measured())
+                .collect()
+        };
+        let time = |algo: &str, repeat: usize| {
+            let rows = measured(algo, repeat);
+            if rows.is_empty() {
+                return None;
+            }
+            let row = fastest(&rows, timeout);
+            Some(Time {
+                micros: row.micros(timeout),
+                timeout: row.timed_out(),
+            })
+        };
+        let failed = |algo: &str, repeat: usize| {
+            if !measured(algo, repeat).is_empty() {
+                return None;
+            }
+            let row = runs.get(&(algo, repeat))?.first()?;
+            Some((row.status(), row.message.clone()))
+        };
+        let result_size = runs
+            .values()
+            .flatten()
+            .find(|r| r.status() == Status::Ok)
+            .map_or(String::new(), |r| r.result_size.clone());
+        Point {
+            size,
+            em: time("EMatch", 0),
+            gj0: time("GenericJoin", 0),
+            gj1: time("GenericJoin", 1),
+            failed: [
+                failed("EMatch", 0),
+                failed("GenericJoin", 0),
+                failed("GenericJoin", 1),
+            ],
+            result_size,
+        }
+    }
+
+    fn times(&self) -> [Option<Time>; 3] {
+        [self.em, self.gj0, self.gj1]
+    }
+
+    /// EM time over GJ time, with the first or the second GJ run.
+    fn ratio(&self, index: usize) -> Option<f64> {
+        let gj = if index == 0 { self.gj0 } else { self.gj1 };
+        Some(self.em?.micros / gj?.micros)
+    }
+}
+
+struct PatternRuns {
+    pattern: String,
+    category: String,
+    /// The anchor of the pattern's curve.
+    anchor: String,
+    points: Vec<Point>,
+}
+
+/// Renders the whole report.
+pub fn render(rows: &[ResultRow], summaries: &[Summary], timeout: Option<f64>) -> String {
+    let mut benches: BTreeMap<String, BTreeMap<&str, BTreeMap<usize, Runs>>> = BTreeMap::new();
+    let mut categories: BTreeMap<(String, &str), &str> = BTreeMap::new();
+    for row in rows {
+        benches
+            .entry(row.bench())
+            .or_default()
+            .entry(&row.pattern)
+            .or_default()
+            .entry(row.node_size)
+            .or_default()
+            .entry((&row.algo, row.repeat_time))
+            .or_default()
+            .push(row);
+        if !row.category.is_empty() {
+            categories.insert((row.bench(), &row.pattern), &row.category);
+        }
+    }
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>E-matching benchmark report</title>\n");
+    writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE).unwrap();
+    html.push_str("<h1>E-matching benchmark report</h1>\n");
+    html.push_str(
+        "<p>Ratios are EM time over GJ time, so above 1 means GJ was faster. \
+         GJ0 includes building the indices, GJ1 reuses them. \
+         <span class=\"timeout\">TO</span> marks a timeout, \
+         which makes the ratio a bound. \
+         <span class=\"failed\">panic</span>, <span class=\"failed\">oom</span> and \
+         <span class=\"failed\">skipped</span> mark searches that failed, \
+         which are left out of the ratios.</p>\n",
+    );
+    summary_table(&mut html, summaries);
+
+    html.push_str("<ul>\n");
+    for bench in benches.keys() {
+        writeln!(
+            html,
+            "<li><a href=\"#{0}\">{1}</a></li>",
+            anchor(bench, ""),
+            escape(bench)
+        )
+        .unwrap();
+    }
+    html.push_str("</ul>\n");
+
+    for (bench, pats) in &benches {
+        let pats: Vec<PatternRuns> = pats
+            .iter()
+            .map(|(pattern, sizes)| PatternRuns {
+                pattern: pattern.to_string(),
+                category: categories
+                    .get(&(bench.clone(), *pattern))
+                    .map_or(String::new(), |c| c.to_string()),
+                anchor: anchor(bench, pattern),
+                points: sizes
+                    .iter()
+                    .map(|(size, runs)| Point::of(*size, runs, timeout))
+                    .collect(),
+            })
+            .collect();
+        let mut sizes: Vec<usize> = pats
+            .iter()
+            .flat_map(|p| p.points.iter().map(|pt| pt.size))
+            .collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        writeln!(
+            html,
+            "<h2 id=\"{}\">{}</h2>",
+            anchor(bench, ""),
+            escape(bench)
+        )
+        .unwrap();
+        for size in &sizes {
+            writeln!(html, "<h3>{} e-nodes</h3>", size).unwrap();
+            speedup_chart(&mut html, &pats, *size);
+            pattern_table(&mut html, &pats, *size);
+        }
+        html.push_str("<h3>Time against e-graph size</h3>\n");
+        for p in &pats {
+            curve(&mut html, p);
+        }
+    }
+
+    writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT).unwrap();
+    html
+}
+
+fn summary_table(html: &mut String, summaries: &[Summary]) {
+    let by_category = summaries.iter().any(|s| s.category.is_some());
+    html.push_str(
+        "<table class=\"sortable\">\n<thead><tr><th>index</th><th>bench</th><th>size</th>",
+    );
+    if by_category {
+        html.push_str("<th>type</th>");
+    }
+    html.push_str(
+        "<th>gj</th><th>em</th><th>TO</th><th>total</th><th>hmean</th><th>gmean</th>\
+         <th>best</th><th>median</th><th>worst</th></tr></thead>\n<tbody>\n",
+    );
+    for s in summaries {
+        write!(
+            html,
+            "<tr><td data-v=\"{}\">{0}</td><td class=\"pattern\"><a href=\"#{}\">{}</a></td><td data-v=\"{3}\">{3}</td>",
+            s.index,
+            anchor(&s.bench, ""),
+            escape(&s.bench),
+            s.size
+        )
+        .unwrap();
+        if let Some(c) = &s.category {
+            write!(html, "<td>{}</td>", escape(c)).unwrap();
+        }
+        let to_class = if s.timeouts > 0 {
+            " class=\"timeout\""
+        } else {
+            ""
+        };
+        write!(
+            html,
+            "<td data-v=\"{0}\">{0}</td><td data-v=\"{1}\">{1}</td><td data-v=\"{2}\"{3}>{2}</td>",
+            s.gj, s.em, s.timeouts, to_class
+        )
+        .unwrap();
+        for x in &[s.total, s.hmean, s.gmean, s.best, s.median, s.worst] {
+            write!(html, "<td data-v=\"{}\">{}</td>", x, fmt_x(*x).trim()).unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+/// Figure 9 for one e-graph: a bar of `log(EM / GJ0)` and one of
+/// `log(EM / GJ1)` per pattern, ordered by the number of results.
+fn speedup_chart(html: &mut String, pats: &[PatternRuns], size: usize) {
+    let mut bars: Vec<(&PatternRuns, &Point)> = pats
+        .iter()
+        .filter_map(|p| Some((p, p.points.iter().find(|pt| pt.size == size)?)))
+        .filter(|(_, pt)| pt.ratio(0).is_some())
+        .collect();
+    if bars.is_empty() {
+        return;
+    }
+    bars.sort_by_key(|(_, pt)| pt.result_size.parse::<u64>().unwrap_or(0));
+
+    let logs = bars.iter().flat_map(|(_, pt)| {
+        vec![pt.ratio(0), pt.ratio(1)]
+            .into_iter()
+            .flatten()
+            .map(f64::log10)
+    });
+    let lo = logs.clone().fold(-1.0, f64::min).floor();
+    let hi = logs.fold(1.0, f64::max).ceil();
+
+    let (label_width, plot_width, row_height) = (420.0, 480.0, 20.0);
+    let top = 20.0;
+    let height = top + row_height * bars.len() as f64 + 30.0;
+    let x = |log: f64| label_width + (log - lo) / (hi - lo) * plot_width;
+    writeln!(
+        html,
+        "<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">",
+        label_width + plot_width + 30.0,
+        height
+    )
+    .unwrap();
+    writeln!(
+        html,
+        "<rect x=\"{}\" y=\"4\" width=\"10\" height=\"8\" fill=\"orange\"/><text x=\"{}\" y=\"12\">EM / GJ0</text>\
+         <rect x=\"{}\" y=\"4\" width=\"10\" height=\"8\" fill=\"steelblue\"/><text x=\"{}\" y=\"12\">EM / GJ1</text>",
+        label_width,
+        label_width + 14.0,
+        label_width + 100.0,
+        label_width + 114.0
+    )
+    .unwrap();
+    for tick in (lo as i32)..=(hi as i32) {
+        let tx = x(tick as f64);
+        let opacity = if tick == 0 { 1.0 } else { 0.2 };
+        writeln!(
+            html,
+            "<line x1=\"{0}\" x2=\"{0}\" y1=\"{1}\" y2=\"{2}\" stroke=\"gray\" stroke-opacity=\"{3}\"/>\
+             <text x=\"{0}\" y=\"{4}\" text-anchor=\"middle\">{5}×</text>",
+            tx,
+            top,
+            height - 22.0,
+            opacity,
+            height - 8.0,
+            power_of_ten(tick)
+        )
+        .unwrap();
+    }
+    for (i, (p, pt)) in bars.iter().enumerate() {
+        let y = top + row_height * i as f64;
+        writeln!(
+            html,
+            "<a href=\"#{}\"><text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}<title>{} results</title></text></a>",
+            p.anchor,
+            label_width - 6.0,
+            y + 13.0,
+            escape(&truncate(&p.pattern, 56)),
+            escape(&pt.result_size)
+        )
+        .unwrap();
+        for (index, color) in [(0, "orange"), (1, "steelblue")].iter() {
+            let log = match pt.ratio(*index) {
+                Some(r) => r.log10(),
+                None => continue,
+            };
+            let (x0, x1) = (x(0.0).min(x(log)), x(0.0).max(x(log)));
+            let by = y + 2.0 + 8.0 * *index as f64;
+            writeln!(
+                html,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"8\" fill=\"{}\"><title>{}</title></rect>",
+                x0,
+                by,
+                (x1 - x0).max(1.0),
+                color,
+                fmt_x(10f64.powf(log)).trim()
+            )
+            .unwrap();
+        }
+        let gj_timeout = [pt.gj0, pt.gj1].iter().flatten().any(|t| t.timeout);
+        if matches!(pt.em, Some(t) if t.timeout) || gj_timeout {
+            let which = if gj_timeout { "GJ" } else { "EM" };
+            let end = [pt.ratio(0), pt.ratio(1)]
+                .iter()
+                .flatten()
+                .map(|r| x(r.log10()))
+                .fold(x(0.0), f64::max);
+            writeln!(
+                html,
+                "<text class=\"timeout\" x=\"{}\" y=\"{}\" fill=\"#c00\">TO<title>{} timed out, so the ratio is a bound</title></text>",
+                end + 4.0,
+                y + 13.0,
+                which
+            )
+            .unwrap();
+        }
+    }
+    html.push_str("</svg>\n");
+}
+
+fn pattern_table(html: &mut String, pats: &[PatternRuns], size: usize) {
+    html.push_str(
+        "<table class=\"sortable\">\n<thead><tr><th>pattern</th><th>type</th><th>results</th>\
+         <th>EM</th><th>GJ0</th><th>GJ1</th><th>EM / GJ0</th><th>EM / GJ1</th></tr></thead>\n<tbody>\n",
+    );
+    for p in pats {
+        let pt = match p.points.iter().find(|pt| pt.size == size) {
+            Some(pt) => pt,
+            None => continue,
+        };
+        write!(
+            html,
+            "<tr><td class=\"pattern\"><a href=\"#{}\">{}</a></td><td>{}</td><td data-v=\"{}\">{}</td>",
+            p.anchor,
+            escape(&p.pattern),
+            escape(&p.category),
+            pt.result_size.parse::<f64>().unwrap_or(0.0),
+            escape(&pt.result_size)
+        )
+        .unwrap();
+        for (t, failed) in pt.times().iter().zip(&pt.failed) {
+            match t {
+                Some(t) if t.timeout => write!(
+                    html,
+                    "<td data-v=\"{}\" class=\"timeout\">{} TO</td>",
+                    t.micros,
+                    fmt_time(t.micros)
+                ),
+                Some(t) => write!(
+                    html,
+                    "<td data-v=\"{}\">{}</td>",
+                    t.micros,
+                    fmt_time(t.micros)
+                ),
+                None => match failed {
+                    Some((status, message)) => write!(
+                        html,
+                        "<td data-v=\"0\" class=\"failed\" title=\"{}\">{}</td>",
+                        escape(message),
+                        status
+                    ),
+                    None => write!(html, "<td data-v=\"0\"></td>"),
+                },
+            }
+            .unwrap();
+        }
+        for index in 0..2 {
+            match pt.ratio(index) {
+                Some(r) => write!(html, "<td data-v=\"{}\">{}</td>", r, fmt_x(r).trim()),
+                None => write!(html, "<td data-v=\"0\"></td>"),
+            }
+            .unwrap();
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+}
+
+/// Time against e-graph size on log-log axes, one line per strategy, with
+/// timeouts as red rings.
+fn curve(html: &mut String, p: &PatternRuns) {
+    let times: Vec<f64> = p
+        .points
+        .iter()
+        .flat_map(|pt| pt.times().to_vec())
+        .flatten()
+        .map(|t| t.micros.log10())
+        .collect();
+    if times.is_empty() {
+        return;
+    }
+    let sizes = p.points.iter().map(|pt| (pt.size.max(1) as f64).log10());
+    let (x_lo, x_hi) = (
+        sizes.clone().fold(f64::INFINITY, f64::min).floor(),
+        sizes.fold(f64::NEG_INFINITY, f64::max).ceil().max(1.0),
+    );
+    let x_hi = if x_hi <= x_lo { x_lo + 1.0 } else { x_hi };
+    let y_lo = times.iter().copied().fold(f64::INFINITY, f64::min).floor();
+    let y_hi = times
+        .iter()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max)
+        .ceil();
+    let y_hi = if y_hi <= y_lo { y_lo + 1.0 } else { y_hi };
+
+    let (left, width, height) = (60.0, 360.0, 180.0);
+    let x = |size: usize| left + ((size.max(1) as f64).log10() - x_lo) / (x_hi - x_lo) * width;
+    let y = |micros: f64| 10.0 + (y_hi - micros.log10()) / (y_hi - y_lo) * height;
+
+    writeln!(html, "<div id=\"{}\">", p.anchor).unwrap();
+    writeln!(
+        html,
+        "<p class=\"pattern\"><code>{}</code> {}</p>",
+        escape(&p.pattern),
+        escape(&p.category)
+    )
+    .unwrap();
+    writeln!(
+        html,
+        "<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">",
+        left + width + 110.0,
+        height + 40.0
+    )
+    .unwrap();
+    for tick in (y_lo as i32)..=(y_hi as i32) {
+        let ty = 10.0 + (y_hi - tick as f64) / (y_hi - y_lo) * height;
+        writeln!(
+            html,
+            "<line x1=\"{0}\" x2=\"{1}\" y1=\"{2}\" y2=\"{2}\" stroke=\"gray\" stroke-opacity=\"0.2\"/>\
+             <text x=\"{3}\" y=\"{4}\" text-anchor=\"end\">{5}</text>",
+            left,
+            left + width,
+            ty,
+            left - 4.0,
+            ty + 4.0,
+            fmt_time(10f64.powi(tick))
+        )
+        .unwrap();
+    }
+    for tick in (x_lo as i32)..=(x_hi as i32) {
+        let tx = left + (tick as f64 - x_lo) / (x_hi - x_lo) * width;
+        writeln!(
+            html,
+            "<line x1=\"{0}\" x2=\"{0}\" y1=\"10\" y2=\"{1}\" stroke=\"gray\" stroke-opacity=\"0.2\"/>\
+             <text x=\"{0}\" y=\"{2}\" text-anchor=\"middle\">{3}</text>",
+            tx,
+            10.0 + height,
+            height + 25.0,
+            power_of_ten(tick)
+        )
+        .unwrap();
+    }
+
+    let strategies = [("EM", "orange"), ("GJ0", "steelblue"), ("GJ1", "seagreen")];
+    for (i, (name, color)) in strategies.iter().enumerate() {
+        let points: Vec<(usize, Time)> = p
+            .points
+            .iter()
+            .filter_map(|pt| Some((pt.size, pt.times()[i]?)))
+            .collect();
+        let line: Vec<String> = points
+            .iter()
+            .map(|(size, t)| format!("{},{}", x(*size), y(t.micros)))
+            .collect();
+        writeln!(
+            html,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\"/>",
+            line.join(" "),
+            color
+        )
+        .unwrap();
+        for (size, t) in &points {
+            let (fill, stroke) = if t.timeout {
+                ("none", "#c00")
+            } else {
+                (*color, *color)
+            };
+            let to = if t.timeout { " (timeout)" } else { "" };
+            writeln!(
+                html,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"{}\" stroke=\"{}\"><title>{} on {} e-nodes: {}{}</title></circle>",
+                x(*size),
+                y(t.micros),
+                fill,
+                stroke,
+                name,
+                size,
+                fmt_time(t.micros),
+                to
+            )
+            .unwrap();
+        }
+        writeln!(
+            html,
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>",
+            left + width + 10.0,
+            20.0 + 14.0 * i as f64,
+            color,
+            name
+        )
+        .unwrap();
+    }
+    html.push_str("</svg>\n</div>\n");
+}
+
+/// An id for the section of a bench, or of one of its patterns.
+fn anchor(bench: &str, pattern: &str) -> String {
+    let bench: String = bench
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    if pattern.is_empty() {
+        bench
+    } else {
+        let pattern = pattern.split_whitespace().collect::<Vec<_>>().join(" ");
+        format!("{}-{:08x}", bench, fnv1a(pattern.bytes()))
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        s.chars().take(max - 1).chain(Some('…')).collect()
+    }
+}
+
+fn power_of_ten(exp: i32) -> String {
+    if (-2..=4).contains(&exp) {
+        10f64.powi(exp).to_string()
+    } else {
+        format!("1e{}", exp)
+    }
+}
+
+/// Like `compare.py`: `12µs`, `3ms`, `4s`.
+fn fmt_time(micros: f64) -> String {
+    if micros >= 1e6 {
+        format!("{:.0}s", micros / 1e6)
+    } else if micros >= 1e3 {
+        format!("{:.0}ms", micros / 1e3)
+    } else {
+        format!("{:.0}µs", micros)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn rows(csv: &str) -> Vec<ResultRow> {
+        let header = "benchmark,node_size,algo,pattern,category,mode,status,time_ns,\
+                      result_size,repeat_time,message\n";
+        csv::Reader::from_reader(format!("{}{}", header, csv).as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(anchor("math/post", ""), "math-post");
+        assert_eq!(anchor("math", "(+ ?a ?b)"), anchor("math", " (+  ?a\n?b) "));
+        assert_ne!(anchor("math", "(+ ?a ?b)"), anchor("math", "(* ?a ?b)"));
+        assert_ne!(anchor("math", "(+ ?a ?b)"), anchor("lambda", "(+ ?a ?b)"));
+        assert!(anchor("math", "(+ ?a ?b)").starts_with("math-"));
+    }
+
+    #[test]
+    fn escapes_text_from_the_results() {
+        let html = render(
+            &rows(
+                "math,1000,EMatch,(< ?a ?b),<b>type</b>,pattern,ok,2000,\"<i>1</i>\",0,\n\
+                 math,1000,GenericJoin,(< ?a ?b),<b>type</b>,pattern,panic,0,0,0,\"<script>\"\n",
+            ),
+            &[],
+            None,
+        );
+        for raw in &["<b>", "<i>", "<script>\"", "(< ?a"] {
+            assert!(!html.contains(raw), "{} isn't escaped", raw);
+        }
+        assert!(html.contains("&lt;b&gt;type&lt;/b&gt;"));
+        assert!(html.contains("(&lt; ?a ?b)"));
+        assert!(html.contains("title=\"&lt;script&gt;\""));
+        assert_eq!(escape("a&\"b\""), "a&amp;&quot;b&quot;");
+    }
+
+    #[test]
+    fn chart_data() {
+        let rows = rows(
+            "math,1000,EMatch,(+ ?a ?b),linear,pattern,ok,4000000,10,0,\n\
+             math,1000,GenericJoin,(+ ?a ?b),linear,pattern,ok,2000000,10,0,\n\
+             math,1000,GenericJoin,(+ ?a ?b),linear,pattern,ok,1000000,10,1,\n\
+             math,1000,EMatch,(* ?a ?b),linear,pattern,timeout,60000000000,0,0,\n\
+             math,1000,GenericJoin,(* ?a ?b),linear,pattern,ok,6000000,5,0,\n",
+        );
+        let mut runs: Runs = HashMap::new();
+        for row in rows.iter().filter(|r| r.pattern == "(+ ?a ?b)") {
+            runs.entry((row.algo.as_str(), row.repeat_time))
+                .or_default()
+                .push(row);
+        }
+        let point = Point::of(1000, &runs, None);
+        assert_eq!(point.em.unwrap().micros, 4000.0);
+        assert_eq!(point.ratio(0), Some(2.0));
+        assert_eq!(point.ratio(1), Some(4.0));
+        assert_eq!(point.result_size, "10");
+
+        let html = render(&rows, &[], None);
+        // the table sorts by the raw times and ratios
+        assert!(html.contains("<td data-v=\"4000\">4ms</td>"));
+        assert!(html.contains("<td data-v=\"2\">2.00</td><td data-v=\"4\">4.00</td>"));
+        // the EM timeout marks its bar and its curve
+        assert!(html.contains("EM timed out, so the ratio is a bound"));
+        assert!(html.contains("(timeout)"));
+        // a bar per pattern for the first GJ run, and a curve per pattern
+        assert_eq!(html.matches("fill=\"orange\"><title>").count(), 2);
+        assert_eq!(html.matches("<polyline").count(), 6);
+        assert!(html.contains(&format!("<div id=\"{}\">", anchor("math", "(+ ?a ?b)"))));
+    }
+}
@@ -3,12 +3,15 @@ use egg::{define_language, rewrite as rw, Id};
 use std::cmp::*;
 use std::collections::*;
 
-pub fn lambda_bench() -> Bench<Lambda, LambdaAnalysis> {
-    Bench {
+pub fn lambda_bench() -> Result<Bench<Lambda, LambdaAnalysis>, Vec<String>> {
+    let (rules, conditionals) = rules();
+    Ok(Bench {
         name: "lambda".into(),
-        rules: rules(),
-        bench_pats: parse_patterns("lambda"),
-        start_exprs: vec![
+        rules,
+        bench_pats: parse_patterns("lambda")?,
+        conditionals,
+        has_data: Some(|data| data.constant.is_some()),
+        start_exprs: [
             "(let zeroone (lam x
                 (if (= (var x) 0)
                     0
@@ -38,8 +41,11 @@ pub fn lambda_bench() -> Bench<Lambda, LambdaAnalysis> {
                     (app (var fib)
                         (+ (var n) -2)))))))
                 (app (var fib) 4))",
-        ],
-    }
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+    })
 }
 
 define_language! {
@@ -148,17 +154,26 @@ fn is_not_same_var(v1: Var, v2: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool
     move |egraph, _, subst| egraph.find(subst[v1]) != egraph.find(subst[v2])
 }
 
-fn is_const(v: Var) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
-    move |egraph, _, subst| egraph[subst[v]].data.constant.is_some()
+fn const_class(egraph: &EGraph, id: Id) -> bool {
+    egraph[id].data.constant.is_some()
 }
 
-fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
-    vec![
+/// The rules, and the searchers of the conditional ones for `--conditional`.
+fn rules() -> (
+    Vec<Rewrite<Lambda, LambdaAnalysis>>,
+    Vec<ConditionalSearcher<Lambda, LambdaAnalysis>>,
+) {
+    let mut c = Conditionals::default();
+    let rules = vec![
         // open term rules
         rw!("if-true";  "(if  true ?then ?else)" => "?then"),
         rw!("if-false"; "(if false ?then ?else)" => "?else"),
-        rw!("if-elim"; "(if (= (var ?x) ?e) ?then ?else)" => "?else"
-            if ConditionEqual::parse("(let ?x ?e ?then)", "(let ?x ?e ?else)")),
+        c.add(
+            ConditionalSearcher::new("if-elim", "(if (= (var ?x) ?e) ?then ?else)").condition(
+                ConditionEqual::parse("(let ?x ?e ?then)", "(let ?x ?e ?else)"),
+            ),
+        )
+        .rewrite("?else"),
         rw!("add-comm";  "(+ ?a ?b)"        => "(+ ?b ?a)"),
         rw!("add-assoc"; "(+ (+ ?a ?b) ?c)" => "(+ ?a (+ ?b ?c))"),
         rw!("eq-comm";   "(= ?a ?b)"        => "(= ?b ?a)"),
@@ -168,25 +183,34 @@ fn rules() -> Vec<Rewrite<Lambda, LambdaAnalysis>> {
         rw!("let-app";  "(let ?v ?e (app ?a ?b))" => "(app (let ?v ?e ?a) (let ?v ?e ?b))"),
         rw!("let-add";  "(let ?v ?e (+   ?a ?b))" => "(+   (let ?v ?e ?a) (let ?v ?e ?b))"),
         rw!("let-eq";   "(let ?v ?e (=   ?a ?b))" => "(=   (let ?v ?e ?a) (let ?v ?e ?b))"),
-        rw!("let-const";
-            "(let ?v ?e ?c)" => "?c" if is_const(var("?c"))),
+        c.add(ConditionalSearcher::new("let-const", "(let ?v ?e ?c)").guard("?c", const_class))
+            .rewrite("?c"),
         rw!("let-if";
             "(let ?v ?e (if ?cond ?then ?else))" =>
             "(if (let ?v ?e ?cond) (let ?v ?e ?then) (let ?v ?e ?else))"
         ),
         rw!("let-var-same"; "(let ?v1 ?e (var ?v1))" => "?e"),
-        rw!("let-var-diff"; "(let ?v1 ?e (var ?v2))" => "(var ?v2)"
-            if is_not_same_var(var("?v1"), var("?v2"))),
+        c.add(
+            ConditionalSearcher::new("let-var-diff", "(let ?v1 ?e (var ?v2))")
+                .condition(is_not_same_var(var("?v1"), var("?v2"))),
+        )
+        .rewrite("(var ?v2)"),
         rw!("let-lam-same"; "(let ?v1 ?e (lam ?v1 ?body))" => "(lam ?v1 ?body)"),
-        rw!("let-lam-diff";
-            "(let ?v1 ?e (lam ?v2 ?body))" =>
-            { CaptureAvoid {
-                fresh: var("?fresh"), v2: var("?v2"), e: var("?e"),
-                if_not_free: "(lam ?v2 (let ?v1 ?e ?body))".parse().unwrap(),
-                if_free: "(lam ?fresh (let ?v1 ?e (let ?v2 (var ?fresh) ?body)))".parse().unwrap(),
-            }}
-            if is_not_same_var(var("?v1"), var("?v2"))),
-    ]
+        c.add(
+            ConditionalSearcher::new("let-lam-diff", "(let ?v1 ?e (lam ?v2 ?body))")
+                .condition(is_not_same_var(var("?v1"), var("?v2"))),
+        )
+        .rewrite_with(CaptureAvoid {
+            fresh: var("?fresh"),
+            v2: var("?v2"),
+            e: var("?e"),
+            if_not_free: "(lam ?v2 (let ?v1 ?e ?body))".parse().unwrap(),
+            if_free: "(lam ?fresh (let ?v1 ?e (let ?v2 (var ?fresh) ?body)))"
+                .parse()
+                .unwrap(),
+        }),
+    ];
+    (rules, c.into_searchers())
 }
 
 struct CaptureAvoid {
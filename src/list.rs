@@ -0,0 +1,116 @@
+//! The `list` subcommand: the benches with their languages, rules and
+//! patterns, to find the ids and filters to pass to a run.
+
+use crate::patterns::{PatternFilter, PatternType};
+use crate::{with_bench, Bench, BenchVisitor, Opt, BENCHES};
+use egg::*;
+use std::fmt::Display;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ListOpt {
+    /// Only list these benches; all by default
+    benches: Vec<String>,
+    /// Only list patterns matching one of these, as with the --pattern of a run
+    #[structopt(long, number_of_values = 1)]
+    pattern: Vec<PatternFilter>,
+    /// Only list patterns of these types (linear, nonlinear, ground)
+    #[structopt(long, value_delimiter = ",")]
+    category: Vec<PatternType>,
+    /// List the left-hand sides of conditional rules instead of the patterns
+    #[structopt(long)]
+    conditional: bool,
+    /// Also list the names of the rules
+    #[structopt(long)]
+    rules: bool,
+}
+
+pub fn list(list_opt: &ListOpt) {
+    let mut names = BENCHES.to_vec();
+    names.sort_unstable();
+    for name in &list_opt.benches {
+        if !names.contains(&name.as_str()) {
+            eprintln!(
+                "error: unknown bench {}, expected one of {}",
+                name,
+                names.join(", ")
+            );
+            std::process::exit(1)
+        }
+    }
+    // the filters of a run, so the list shows exactly what it would bench
+    let mut opt = Opt::from_iter(&["egg-bench"]);
+    opt.pattern = list_opt.pattern.clone();
+    opt.category = list_opt.category.clone();
+    opt.conditional = list_opt.conditional;
+    for name in names {
+        if list_opt.benches.is_empty() || list_opt.benches.iter().any(|b| b == name) {
+            let describe = Describe {
+                list_opt,
+                opt: &opt,
+            };
+            if let Err(problems) = with_bench(&opt, name, describe) {
+                for p in problems {
+                    eprintln!("error: {}", p);
+                }
+                std::process::exit(1)
+            }
+        }
+    }
+}
+
+struct Describe<'a> {
+    list_opt: &'a ListOpt,
+    opt: &'a Opt,
+}
+
+impl BenchVisitor for Describe<'_> {
+    fn visit<L, A>(self, bench: Bench<L, A>, _: Option<fn(usize) -> EGraph<L, A>>)
+    where
+        A: Analysis<L> + Default + Clone + Send + Sync,
+        L: Language + FromOp + Sync + Send + Display,
+        <A as egg::Analysis<L>>::Data: Send + Clone,
+        <L as egg::Language>::Operator: Send + Sync,
+    {
+        describe(self.list_opt, self.opt, bench)
+    }
+}
+
+fn describe<L, A>(list_opt: &ListOpt, opt: &Opt, bench: Bench<L, A>)
+where
+    A: Analysis<L>,
+    L: Language + Display,
+{
+    let language = std::any::type_name::<L>().rsplit("::").next().unwrap();
+    let searches = bench.searches(opt.conditional);
+    let selected: Vec<_> = searches
+        .iter()
+        .filter(|(id, category, pattern)| opt.selects(&bench.name, id, *category, pattern))
+        .collect();
+    println!(
+        "{} ({}, {} rules, {} of {} {})",
+        bench.name,
+        language,
+        bench.rules.len(),
+        selected.len(),
+        searches.len(),
+        if opt.conditional {
+            "conditional rules"
+        } else {
+            "patterns"
+        }
+    );
+    if list_opt.rules {
+        let rules: Vec<&str> = bench.rules.iter().map(|r| r.name()).collect();
+        println!("  rules: {}", rules.join(", "));
+    }
+    for (id, category, pattern) in selected {
+        let category = category.to_string();
+        println!(
+            "  {:<24} {:<9} {}",
+            id,
+            category,
+            pattern.pretty(usize::MAX)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! A dense simplex solver, big enough for the LPs of pattern hypergraphs,
+//! which have one row per atom and one column per query variable.
+
+const EPS: f64 = 1e-9;
+
+/// Maximizes `c · y` subject to `a y <= b` and `y >= 0`.
+/// Every entry of `b` has to be non-negative, so `y = 0` is a feasible start.
+/// Returns infinity if the LP is unbounded.
+pub fn maximize(c: &[f64], a: &[Vec<f64>], b: &[f64]) -> f64 {
+    let (m, n) = (a.len(), c.len());
+    assert_eq!(m, b.len());
+    assert!(b.iter().all(|&b| b >= 0.0), "y = 0 must be feasible");
+
+    // each row is [a | identity for the slacks | b]
+    let mut tableau: Vec<Vec<f64>> = (0..m)
+        .map(|i| {
+            assert_eq!(a[i].len(), n);
+            let mut row = a[i].clone();
+            row.resize(n + m, 0.0);
+            row[n + i] = 1.0;
+            row.push(b[i]);
+            row
+        })
+        .collect();
+    // reduced costs, the last entry is minus the objective value
+    let mut cost = c.to_vec();
+    cost.resize(n + m + 1, 0.0);
+    let mut basis: Vec<usize> = (n..n + m).collect();
+
+    loop {
+        // Bland's rule: the lowest improving column and, among the rows with
+        // the tightest ratio, the lowest basic variable, so it never cycles
+        let col = match (0..n + m).find(|&j| cost[j] > EPS) {
+            Some(col) => col,
+            None => return 0.0 - cost[n + m],
+        };
+        let ratio = |i: usize| tableau[i][n + m] / tableau[i][col];
+        let row = (0..m).filter(|&i| tableau[i][col] > EPS).min_by(|&i, &k| {
+            let (ri, rk) = (ratio(i), ratio(k));
+            if (ri - rk).abs() <= EPS {
+                basis[i].cmp(&basis[k])
+            } else {
+                ri.partial_cmp(&rk).unwrap()
+            }
+        });
+        let row = match row {
+            Some(row) => row,
+            None => return f64::INFINITY,
+        };
+
+        let pivot = tableau[row][col];
+        tableau[row].iter_mut().for_each(|x| *x /= pivot);
+        let pivot_row = tableau[row].clone();
+        for (i, r) in tableau.iter_mut().enumerate() {
+            let f = r[col];
+            if i != row && f != 0.0 {
+                r.iter_mut().zip(&pivot_row).for_each(|(x, p)| *x -= f * p);
+            }
+        }
+        let f = cost[col];
+        cost.iter_mut()
+            .zip(&pivot_row)
+            .for_each(|(x, p)| *x -= f * p);
+        basis[row] = col;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn bounded() {
+        // x <= 1, y <= 2, x + y <= 2.5
+        let a = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![1.0, 1.0]];
+        assert!(close(maximize(&[1.0, 1.0], &a, &[1.0, 2.0, 2.5]), 2.5));
+        assert!(close(maximize(&[2.0, 1.0], &a, &[1.0, 2.0, 2.5]), 3.5));
+    }
+
+    #[test]
+    fn unbounded() {
+        assert_eq!(maximize(&[1.0], &[vec![-1.0]], &[1.0]), f64::INFINITY);
+    }
+
+    #[test]
+    fn degenerate() {
+        // every constraint is tight at y = 0
+        let a = vec![vec![1.0, -1.0], vec![-1.0, 1.0], vec![1.0, 1.0]];
+        assert!(close(maximize(&[1.0, 1.0], &a, &[0.0, 0.0, 0.0]), 0.0));
+    }
+
+    #[test]
+    fn no_constraints_no_objective() {
+        assert!(close(maximize(&[], &[], &[]), 0.0));
+    }
+}
@@ -1,167 +1,750 @@
 use egg::*;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::sync::mpsc;
 use std::thread;
 use std::time::*;
 use std::*;
 
+mod adversarial;
+mod bitvector;
+mod bool;
+mod compare;
+mod conditional;
+mod debruijn;
+mod environment;
+mod experiment;
+mod fpcore;
+mod halide;
+mod html;
 mod lambda;
+mod list;
+mod lp;
 mod math;
+mod metrics;
+mod patterns;
+mod relational;
+mod report;
+mod run_one;
+mod shard;
+mod stats;
+mod status;
+mod tensor;
+mod trace;
+
+use conditional::{ConditionalSearcher, Conditionals};
+use metrics::{Hypergraph, PatternMetrics, RelationSizes};
+use patterns::{fnv1a, parse_patterns, BenchPattern, PatternFilter, PatternType};
+use serde_json::json;
+use status::Status;
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub struct Bench<L: Language, A: Analysis<L> + 'static> {
     name: String,
-    start_exprs: Vec<&'static str>,
+    start_exprs: Vec<String>,
     rules: Vec<Rewrite<L, A>>,
-    bench_pats: Vec<Pattern<L>>,
-}
-
-fn parse_patterns<L: FromOp>(bench_name: &str) -> Vec<Pattern<L>> {
-    let file = File::open("patterns.csv").unwrap();
-    let reader = BufReader::new(file);
-    let mut pats = vec![];
-    for line in reader.lines().skip(1) {
-        let line = line.unwrap();
-        let line = line.trim();
-        if !(line.is_empty() || line.starts_with('#')) {
-            let fields: Vec<_> = line.split(',').map(|s| s.trim()).collect();
-            if fields[0] == bench_name {
-                let pat_string = fields.last().unwrap();
-                pats.push(pat_string.parse().unwrap())
+    bench_pats: Vec<BenchPattern<L>>,
+    conditionals: Vec<ConditionalSearcher<L, A>>,
+    /// Whether an e-class carries analysis data, counted in the stats file.
+    has_data: Option<fn(&A::Data) -> bool>,
+}
+
+impl<L: Language, A: Analysis<L>> Bench<L, A> {
+    /// The ids, types and patterns of the searches of the bench: its patterns,
+    /// or with `conditional` the left-hand sides of its conditional rules.
+    pub fn searches(&self, conditional: bool) -> Vec<(String, PatternType, &Pattern<L>)> {
+        if conditional {
+            self.conditionals
+                .iter()
+                .map(|c| {
+                    let id = format!("{}-{}", self.name, c.name);
+                    (id, PatternType::of(&c.pattern).0, &c.pattern)
+                })
+                .collect()
+        } else {
+            self.bench_pats
+                .iter()
+                .map(|p| (p.id.clone(), p.category, &p.pattern))
+                .collect()
+        }
+    }
+}
+
+/// A file that goes with a results file, named after its stem, like
+/// `out/benchmark-env.json` for `out/benchmark.csv`.
+pub fn sidecar(results: &path::Path, suffix: &str) -> path::PathBuf {
+    let stem = results.file_stem().unwrap_or_default().to_string_lossy();
+    results.with_file_name(format!("{}-{}", stem, suffix))
+}
+
+/// Where a run writes the timings and the e-graph stats.
+pub struct Output {
+    results: Results,
+    stats: csv::Writer<std::fs::File>,
+    /// Which of the `--samples` is running.
+    sample: usize,
+    /// See `Opt::config_hash`.
+    config_hash: String,
+    trace: trace::Trace,
+    /// The threads of searches that were given up on while they still ran,
+    /// see `time_search`.
+    abandoned: Vec<thread::JoinHandle<()>>,
+}
+
+/// The format of the results file, see `--format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultsFormat {
+    Csv,
+    Jsonl,
+}
+
+impl std::str::FromStr for ResultsFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ResultsFormat::Csv),
+            "jsonl" => Ok(ResultsFormat::Jsonl),
+            _ => Err(format!("unknown format {}, expected csv or jsonl", s)),
+        }
+    }
+}
+
+impl Display for ResultsFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ResultsFormat::Csv => "csv",
+            ResultsFormat::Jsonl => "jsonl",
+        })
+    }
+}
+
+/// The results file, in the format chosen with `--format`.
+pub enum Results {
+    Csv(Box<csv::Writer<std::fs::File>>),
+    /// One JSON object per line, the first of which describes the run.
+    Jsonl(io::BufWriter<std::fs::File>),
+}
+
+impl Results {
+    fn create(opt: &Opt, env: &environment::Environment) -> io::Result<Self> {
+        match opt.format {
+            ResultsFormat::Csv => Ok(Results::Csv(Box::new(csv::Writer::from_path(
+                &opt.filename,
+            )?))),
+            ResultsFormat::Jsonl => {
+                let mut file = io::BufWriter::new(fs::File::create(&opt.filename)?);
+                let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                let header = serde_json::json!({
+                    "type": "header",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "started": started.as_secs(),
+                    "environment": env,
+                    "opt": opt,
+                    "config_hash": opt.config_hash(),
+                });
+                writeln!(file, "{}", header)?;
+                file.flush()?;
+                Ok(Results::Jsonl(file))
+            }
+        }
+    }
+
+    /// Writes a record. The JSON version adds the rest of `run`.
+    fn write(&mut self, record: BenchRecord, run: &RunInfo) {
+        match self {
+            Results::Csv(wtr) => {
+                wtr.serialize(record).unwrap();
+                wtr.flush().unwrap();
+            }
+            Results::Jsonl(file) => {
+                let json = JsonRecord {
+                    kind: "result",
+                    record: &record,
+                    sample: run.sample,
+                    stop_reason: run.stop_reason,
+                    opt: run.opt,
+                };
+                writeln!(file, "{}", serde_json::to_string(&json).unwrap()).unwrap();
+                file.flush().unwrap();
             }
         }
     }
-    pats
 }
 
-use serde::Serialize;
+/// What the JSON records know about a search besides its `BenchRecord`.
+struct RunInfo<'a> {
+    sample: usize,
+    /// Why the runner stopped growing the e-graph.
+    stop_reason: &'a str,
+    opt: &'a Opt,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    record: &'a BenchRecord,
+    sample: usize,
+    stop_reason: &'a str,
+    opt: &'a Opt,
+}
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
 
 #[derive(Debug, Serialize)]
 pub struct BenchRecord {
     benchmark: String,
+    /// The --sizes entry the e-graph was grown to, or the n of generated suites.
+    node_limit: usize,
     node_size: usize,
     class_size: usize,
+    /// Identifies the e-graph across runs, see `RelationSizes::fingerprint`.
+    fingerprint: String,
+    /// Identifies the options of the run, see `Opt::config_hash`.
+    config_hash: String,
+    /// Which part of the run this is, see `--shard`.
+    shard: shard::Shard,
     algo: String,
+    pattern_id: String,
     pattern: String,
-    time: String,
+    category: PatternType,
+    consts: usize,
+    mode: &'static str,
+    status: Status,
+    /// How long the search took; the timeout for timeouts, 0 if skipped.
+    time_ns: u64,
     result_size: usize,
+    /// The most results the pattern could have given the relation sizes.
+    agm_bound: f64,
+    survivors: usize,
     repeat_time: usize,
+    atoms: usize,
+    query_vars: usize,
+    hypergraph_size: usize,
+    distinct_vars: usize,
+    repeated_vars: usize,
+    acyclic: bool,
+    depth: usize,
+    edge_cover: f64,
+    /// The panic message, what made the search fail or get skipped, or why
+    /// its memory wasn't checked.
+    message: String,
+}
+
+pub fn run_bench<L, A>(opt: &Opt, bench: Bench<L, A>, strategies: &[Strategy], out: &mut Output)
+where
+    A: Analysis<L> + Default + Clone + Send + Sync,
+    L: Language + FromOp + Sync + Send + Display,
+    <A as egg::Analysis<L>>::Data: Send + Clone,
+    <L as egg::Language>::Operator: Send + Sync,
+{
+    let last = match opt.last_size(&bench, &opt.sizes) {
+        Some(last) => last,
+        None => return,
+    };
+    let mut egraph: EGraph<L, A> = Default::default();
+    for node_limit in &opt.sizes[..=last] {
+        let start = Instant::now();
+        let runner = saturate(&bench, egraph, *node_limit);
+        runner.print_report();
+        let stop_reason = format!("{:?}", runner.stop_reason);
+        trace_iterations(&mut out.trace, start, &runner.iterations);
+        out.trace.span(
+            "saturate",
+            "runner",
+            start,
+            json!({
+                "bench": bench.name,
+                "node_limit": node_limit,
+                "nodes": runner.egraph.total_number_of_nodes(),
+                "iterations": runner.iterations.len(),
+                "stop_reason": stop_reason,
+            }),
+        );
+        egraph = runner.egraph;
+        // every sample builds the same e-graphs, so their stats are written once
+        if out.sample == 0 {
+            let start = Instant::now();
+            stats::write_stats(&mut out.stats, &bench.name, &egraph, bench.has_data);
+            out.trace
+                .span("stats", "stats", start, json!({ "bench": bench.name }));
+        }
+        let step = Step {
+            node_limit: *node_limit,
+            stop_reason: &stop_reason,
+        };
+        bench_patterns(opt, &bench, &egraph, strategies, &step, out);
+        if let Some(StopReason::Saturated) = runner.stop_reason {
+            break;
+        }
+    }
 }
 
-pub fn run_bench<L, A>(
+/// Grows the e-graph with the bench's start expressions and rules until it
+/// has `node_limit` e-nodes or saturates.
+fn saturate<L, A>(bench: &Bench<L, A>, mut egraph: EGraph<L, A>, node_limit: usize) -> Runner<L, A>
+where
+    A: Analysis<L> + Default,
+    L: Language + FromOp,
+{
+    egraph.strategy = Strategy::GenericJoin;
+    let mut runner: Runner<L, A> = egg::Runner::default().with_egraph(egraph);
+    for expr in &bench.start_exprs {
+        runner = runner.with_expr(&expr.parse().unwrap());
+    }
+    runner
+        .with_node_limit(node_limit)
+        .with_iter_limit(1000)
+        .with_time_limit(std::time::Duration::from_secs(4000))
+        .run(&bench.rules)
+}
+
+/// Adds the runner's iterations to the trace, each split into its search,
+/// apply and rebuild phases. The runner only records how long they took, so
+/// they're laid out back to back from `start`.
+fn trace_iterations<D>(trace: &mut trace::Trace, start: Instant, iterations: &[Iteration<D>]) {
+    let mut at = start;
+    for (i, iteration) in iterations.iter().enumerate() {
+        let applied: usize = iteration.applied.values().sum();
+        let args = json!({
+            "iteration": i,
+            "nodes": iteration.egraph_nodes,
+            "classes": iteration.egraph_classes,
+            "applied": applied,
+            "rebuilds": iteration.n_rebuilds,
+        });
+        let total = Duration::from_secs_f64(iteration.total_time);
+        trace.event("iteration", "runner", at, total, args);
+        let phases = [
+            ("search", iteration.search_time),
+            ("apply", iteration.apply_time),
+            ("rebuild", iteration.rebuild_time),
+        ];
+        let mut phase_start = at;
+        for (name, secs) in phases.iter() {
+            let duration = Duration::from_secs_f64(*secs);
+            trace.event(
+                name,
+                "runner",
+                phase_start,
+                duration,
+                json!({ "iteration": i }),
+            );
+            phase_start += duration;
+        }
+        at += total;
+    }
+}
+
+/// Like `run_bench`, but instead of growing one e-graph with rewrites,
+/// builds a fresh e-graph with `generate(n)` for every n in `--params`.
+pub fn run_generated_bench<L, A>(
     opt: &Opt,
     bench: Bench<L, A>,
+    generate: fn(usize) -> EGraph<L, A>,
     strategies: &[Strategy],
-    wtr: &mut csv::Writer<std::fs::File>,
+    out: &mut Output,
 ) where
     A: Analysis<L> + Default + Clone + Send + Sync,
     L: Language + FromOp + Sync + Send + Display,
     <A as egg::Analysis<L>>::Data: Send + Clone,
     <L as egg::Language>::Operator: Send + Sync,
 {
-    let rules = bench.rules;
-    let pats = bench.bench_pats;
-    let mut egraph: EGraph<L, A> = Default::default();
-    for node_limit in &opt.sizes {
-        egraph.strategy = Strategy::GenericJoin;
-        let mut runner: Runner<L, A> = egg::Runner::default().with_egraph(egraph);
-        for expr in &bench.start_exprs {
-            runner = runner.with_expr(&expr.parse().unwrap());
-        }
-
-        let runner = runner
-            .with_node_limit(*node_limit)
-            .with_iter_limit(1000)
-            .with_time_limit(std::time::Duration::from_secs(4000))
-            .run(&rules);
-        runner.print_report();
-        egraph = runner.egraph;
-        for pat in &pats {
-            let mut em_time = None;
-            let mut gj_time = None;
-            for strategy in strategies {
-                egraph.strategy = *strategy;
-                let repeat = if strategy == &Strategy::GenericJoin {
-                    2
+    let last = match opt.last_size(&bench, &opt.params) {
+        Some(last) => last,
+        None => return,
+    };
+    for n in &opt.params[..=last] {
+        let start = Instant::now();
+        let egraph = generate(*n);
+        let args = json!({ "bench": bench.name, "n": n });
+        out.trace.span("generate", "runner", start, args);
+        eprintln!(
+            "Generated {} e-graph with n = {}: {} nodes, {} classes",
+            bench.name,
+            n,
+            egraph.total_number_of_nodes(),
+            egraph.number_of_classes()
+        );
+        if out.sample == 0 {
+            stats::write_stats(&mut out.stats, &bench.name, &egraph, bench.has_data);
+        }
+        let step = Step {
+            node_limit: *n,
+            stop_reason: "Generated",
+        };
+        bench_patterns(opt, &bench, &egraph, strategies, &step, out);
+    }
+}
+
+/// Which e-graph of a bench the searches run on.
+struct Step<'a> {
+    /// The --sizes entry, or the n of generated suites.
+    node_limit: usize,
+    /// Why the runner stopped growing the e-graph.
+    stop_reason: &'a str,
+}
+
+/// Returns the number of matches and how many of them pass the conditions.
+type Search<L, A> = dyn Fn(&mut EGraph<L, A>) -> (usize, usize) + Send + Sync;
+
+/// Returns the e-graph to search instead of the bench's.
+type Filter<L, A> = dyn Fn(&EGraph<L, A>) -> EGraph<L, A> + Send + Sync;
+
+/// One search to time: a pattern from patterns.csv, or the left-hand side of
+/// a conditional rule together with its conditions.
+struct Query<L: Language, A: Analysis<L>> {
+    id: String,
+    pattern: Pattern<L>,
+    category: PatternType,
+    consts: usize,
+    mode: &'static str,
+    search: Arc<Search<L, A>>,
+    /// With --pushdown, builds the filtered copy of the e-graph to search,
+    /// see `ConditionalSearcher::pushdown`. The AGM bound is computed on the
+    /// copy, while the e-graph columns still describe the bench's.
+    filter: Option<Arc<Filter<L, A>>>,
+}
+
+/// Times the bench's patterns, or with `--conditional` its conditional rules.
+fn bench_patterns<L, A>(
+    opt: &Opt,
+    bench: &Bench<L, A>,
+    egraph: &EGraph<L, A>,
+    strategies: &[Strategy],
+    step: &Step,
+    out: &mut Output,
+) where
+    A: Analysis<L> + Default + Clone + Send + Sync + 'static,
+    L: Language + FromOp + Sync + Send + Display,
+    <A as egg::Analysis<L>>::Data: Send + Clone,
+    <L as egg::Language>::Operator: Send + Sync,
+{
+    let mut queries: Vec<Query<L, A>> = if opt.conditional {
+        let pushdown = opt.pushdown;
+        bench
+            .conditionals
+            .iter()
+            .map(|c| {
+                let (category, consts) = PatternType::of(&c.pattern);
+                let searcher = c.clone();
+                let filter = if pushdown {
+                    let searcher = c.clone();
+                    let filter = move |egraph: &EGraph<L, A>| searcher.pushdown(egraph);
+                    Some(Arc::new(filter) as Arc<Filter<L, A>>)
                 } else {
-                    1
+                    None
                 };
-                for repeat_time in 0..repeat {
-                    // todo: add timeout
-                    let (sender, receiver) = mpsc::channel();
-
-                    let name = bench.name.clone();
-                    {
-                        let pat = pat.clone();
-                        let egraph = egraph.clone();
-                        thread::spawn(move || {
-                            let time = std::time::Instant::now();
-                            // let res = pat.search_with_limit(&egraph, 10_000_000);
-                            let res = pat.search(&egraph);
-                            // let res = pat.search_with_limit(&egraph, usize::MAX);
-                            sender
-                                .send((time.elapsed().as_micros(), res))
-                                .unwrap_or_default()
-                        });
-                    }
-                    let timeout = Duration::from_secs_f64(opt.timeout);
-                    let (time, result_size) = receiver
-                        .recv_timeout(timeout)
-                        .map(|(time, res)| {
-                            (
-                                time.to_string(),
-                                res.iter().map(|m| m.substs.len()).sum::<usize>(),
-                            )
-                        })
-                        // timeouts are printed as negative
-                        .unwrap_or((format!("-{}", timeout.as_micros()), 0));
+                Query {
+                    id: format!("{}-{}", bench.name, c.name),
+                    pattern: c.pattern.clone(),
+                    category,
+                    consts,
+                    mode: if pushdown { "pushdown" } else { "post" },
+                    search: Arc::new(move |egraph: &mut EGraph<L, A>| searcher.search(egraph)),
+                    filter,
+                }
+            })
+            .collect()
+    } else {
+        bench
+            .bench_pats
+            .iter()
+            .map(|p| {
+                let pattern = p.pattern.clone();
+                Query {
+                    id: p.id.clone(),
+                    pattern: p.pattern.clone(),
+                    category: p.category,
+                    consts: p.consts,
+                    mode: "pattern",
+                    search: Arc::new(move |egraph: &mut EGraph<L, A>| {
+                        let n = pattern.search(egraph).iter().map(|m| m.substs.len()).sum();
+                        (n, n)
+                    }),
+                    filter: None,
+                }
+            })
+            .collect()
+    };
+    queries.retain(|q| {
+        opt.selects(&bench.name, &q.id, q.category, &q.pattern)
+            && opt.owns(&bench.name, step.node_limit, &q.id)
+    });
+    bench_queries(opt, &bench.name, &queries, egraph, strategies, step, out)
+}
 
-                    match strategy {
-                        Strategy::EMatch => em_time = Some(time.clone()),
-                        Strategy::GenericJoin => gj_time = Some(time.clone()),
-                    }
+/// How a search ended.
+struct Outcome {
+    status: Status,
+    time_ns: u64,
+    result_size: usize,
+    survivors: usize,
+    message: String,
+    /// The search's thread, if the search was given up on while it still ran.
+    abandoned: Option<thread::JoinHandle<()>>,
+}
 
-                    let record = BenchRecord {
-                        benchmark: name,
-                        node_size: egraph.total_number_of_nodes(),
-                        class_size: egraph.number_of_classes(),
-                        algo: format!("{:?}", egraph.strategy),
-                        pattern: pat.pretty(usize::MAX),
-                        time,
-                        result_size,
-                        repeat_time,
-                    };
-                    if opt.verbose {
-                        eprintln!("{:?}", record);
-                    }
-                    wtr.serialize(record).unwrap();
-                    wtr.flush().unwrap();
+impl Outcome {
+    fn skipped(message: &str) -> Self {
+        Outcome {
+            status: Status::Skipped,
+            time_ns: 0,
+            result_size: 0,
+            survivors: 0,
+            message: message.into(),
+            abandoned: None,
+        }
+    }
+}
+
+/// Runs a search on its own thread, so it can be abandoned when it times out
+/// or the process grows by more than `--memory-limit` while it runs, and a
+/// panic is caught instead of taking the run down.
+///
+/// The limit is on the growth over the resident memory when the search
+/// starts. An abandoned search can't be stopped, so its thread keeps running
+/// and allocating, and that growth would be charged to later searches. With
+/// `--memory-limit`, its thread is thus handed back in the outcome, and while
+/// it runs `check_memory` is false and later searches are timed without the
+/// limit, see `bench_queries`.
+fn time_search<L, A>(
+    opt: &Opt,
+    search: Arc<Search<L, A>>,
+    mut egraph: EGraph<L, A>,
+    check_memory: bool,
+) -> Outcome
+where
+    A: Analysis<L> + Send + 'static,
+    L: Language + Send + 'static,
+    <A as egg::Analysis<L>>::Data: Send,
+    <L as egg::Language>::Operator: Send,
+{
+    let start_kb = opt
+        .memory_limit
+        .filter(|_| check_memory)
+        .and(status::rss_kb());
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let time = Instant::now();
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| search(&mut egraph)));
+        sender
+            .send((
+                time.elapsed().as_nanos() as u64,
+                res.map_err(status::panic_message),
+            ))
+            .unwrap_or_default()
+    });
+
+    let timeout = Duration::from_secs_f64(opt.timeout);
+    let limit_kb = opt.memory_limit.map(|mb| mb * 1024);
+    let deadline = Instant::now() + timeout;
+    let failed = |status, time_ns, message: String, abandoned| Outcome {
+        status,
+        time_ns,
+        result_size: 0,
+        survivors: 0,
+        message,
+        abandoned,
+    };
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        // with a memory limit, wake up now and then to check it
+        let wait = match start_kb {
+            Some(_) => left.min(Duration::from_millis(10)),
+            None => left,
+        };
+        match receiver.recv_timeout(wait) {
+            Ok((time_ns, Ok((result_size, survivors)))) => {
+                return Outcome {
+                    status: Status::Ok,
+                    time_ns,
+                    result_size,
+                    survivors,
+                    message: String::new(),
+                    abandoned: None,
                 }
             }
+            Ok((time_ns, Err(message))) => return failed(Status::Panic, time_ns, message, None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return failed(Status::Panic, 0, "the search thread died".into(), None)
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+        }
+        if Instant::now() >= deadline {
+            let time_ns = timeout.as_nanos() as u64;
+            let handle = opt.memory_limit.map(|_| handle);
+            return failed(Status::Timeout, time_ns, String::new(), handle);
+        }
+        if let (Some(limit), Some(start), Some(rss)) = (limit_kb, start_kb, status::rss_kb()) {
+            if rss.saturating_sub(start) > limit {
+                let elapsed = timeout - deadline.saturating_duration_since(Instant::now());
+                let message = format!(
+                    "resident memory grew from {} MB to {} MB",
+                    start / 1024,
+                    rss / 1024
+                );
+                let time_ns = elapsed.as_nanos() as u64;
+                return failed(Status::Oom, time_ns, message, Some(handle));
+            }
+        }
+    }
+}
 
-            if opt.verbose {
-                if let (Some(gj), Some(em)) = (gj_time, em_time) {
-                    if let (Ok(gj), Ok(em)) = (gj.parse::<f64>(), em.parse::<f64>()) {
-                        let ratio = gj.abs() / em.abs();
-                        if ratio > 1.0 {
-                            println!("!!!!!!! BAD ratio: {}\n\n", ratio);
-                        } else {
-                            println!("        OK  ratio: {}", ratio);
-                        }
+fn bench_queries<L, A>(
+    opt: &Opt,
+    name: &str,
+    queries: &[Query<L, A>],
+    egraph: &EGraph<L, A>,
+    strategies: &[Strategy],
+    step: &Step,
+    out: &mut Output,
+) where
+    A: Analysis<L> + Default + Clone + Send + Sync + 'static,
+    L: Language + FromOp + Sync + Send + Display,
+    <A as egg::Analysis<L>>::Data: Send + Clone,
+    <L as egg::Language>::Operator: Send + Sync,
+{
+    let sizes = RelationSizes::of(egraph);
+    let fingerprint = sizes.fingerprint();
+    for query in queries {
+        let filtered = query.filter.as_ref().map(|filter| {
+            let start = Instant::now();
+            let filtered = filter(egraph);
+            let args = json!({ "nodes": filtered.total_number_of_nodes() });
+            out.trace.span("pushdown", "search", start, args);
+            let sizes = RelationSizes::of(&filtered);
+            (filtered, sizes)
+        });
+        let (searched, searched_sizes) = match &filtered {
+            Some((filtered, sizes)) => (filtered, sizes),
+            None => (egraph, &sizes),
+        };
+        let metrics = PatternMetrics::of(&query.pattern);
+        let agm_bound = Hypergraph::of(&query.pattern).agm_bound(searched_sizes);
+        let mut em_time = None;
+        let mut gj_time = None;
+        let mut gj_failed = false;
+        let query_start = Instant::now();
+        for strategy in strategies {
+            let repeat = if strategy == &Strategy::GenericJoin {
+                2
+            } else {
+                1
+            };
+            for repeat_time in 0..repeat {
+                // the memory of abandoned searches that still run can't be
+                // told apart from this one's
+                out.abandoned.retain(|thread| !thread.is_finished());
+                let running = out.abandoned.len();
+                let gj_skipped = strategy == &Strategy::GenericJoin && repeat_time > 0 && gj_failed;
+                let mut outcome = if gj_skipped {
+                    Outcome::skipped("the first GenericJoin search failed")
+                } else {
+                    let start = Instant::now();
+                    let mut copy = searched.clone();
+                    copy.strategy = *strategy;
+                    out.trace.span(
+                        "clone",
+                        "search",
+                        start,
+                        json!({ "nodes": searched.total_number_of_nodes() }),
+                    );
+                    let start = Instant::now();
+                    let mut outcome = time_search(opt, query.search.clone(), copy, running == 0);
+                    if running > 0 && outcome.message.is_empty() {
+                        outcome.message = format!(
+                            "not checked against --memory-limit, {} abandoned searches still ran",
+                            running
+                        );
+                    }
+                    let args = json!({
+                        "strategy": format!("{:?}", strategy),
+                        "repeat": repeat_time,
+                        "status": outcome.status,
+                        "result_size": outcome.result_size,
+                    });
+                    out.trace.span("search", "search", start, args);
+                    outcome
+                };
+                out.abandoned.extend(outcome.abandoned.take());
+                if strategy == &Strategy::GenericJoin && repeat_time == 0 {
+                    gj_failed = !outcome.status.is_measured();
+                }
+                if outcome.status == Status::Ok {
+                    match strategy {
+                        Strategy::EMatch => em_time = Some(outcome.time_ns),
+                        Strategy::GenericJoin => gj_time = Some(outcome.time_ns),
                     }
                 }
+
+                let record = BenchRecord {
+                    benchmark: name.to_string(),
+                    node_limit: step.node_limit,
+                    node_size: egraph.total_number_of_nodes(),
+                    class_size: egraph.number_of_classes(),
+                    fingerprint: fingerprint.clone(),
+                    config_hash: out.config_hash.clone(),
+                    shard: opt.shard,
+                    algo: format!("{:?}", strategy),
+                    pattern_id: query.id.clone(),
+                    pattern: query.pattern.pretty(usize::MAX),
+                    category: query.category,
+                    consts: query.consts,
+                    mode: query.mode,
+                    status: outcome.status,
+                    time_ns: outcome.time_ns,
+                    result_size: outcome.result_size,
+                    agm_bound,
+                    survivors: outcome.survivors,
+                    repeat_time,
+                    atoms: metrics.atoms,
+                    query_vars: metrics.query_vars,
+                    hypergraph_size: metrics.hypergraph_size,
+                    distinct_vars: metrics.distinct_vars,
+                    repeated_vars: metrics.repeated_vars,
+                    acyclic: metrics.acyclic,
+                    depth: metrics.depth,
+                    edge_cover: metrics.edge_cover,
+                    message: outcome.message,
+                };
+                if opt.verbose {
+                    eprintln!("{:?}", record);
+                }
+                let run = RunInfo {
+                    sample: out.sample,
+                    stop_reason: step.stop_reason,
+                    opt,
+                };
+                out.results.write(record, &run);
             }
         }
-        if let Some(StopReason::Saturated) = runner.stop_reason {
-            break;
+        let args = json!({
+            "bench": name,
+            "pattern_id": query.id,
+            "pattern": query.pattern.pretty(usize::MAX),
+            "nodes": egraph.total_number_of_nodes(),
+        });
+        out.trace.span("pattern", "search", query_start, args);
+
+        if opt.verbose {
+            if let (Some(gj), Some(em)) = (gj_time, em_time) {
+                let ratio = gj as f64 / em as f64;
+                if ratio > 1.0 {
+                    println!("!!!!!!! BAD ratio: {}\n\n", ratio);
+                } else {
+                    println!("        OK  ratio: {}", ratio);
+                }
+            }
         }
     }
 }
 
 use structopt::StructOpt;
-#[derive(Debug, StructOpt)]
+#[derive(Debug, StructOpt, Serialize)]
 pub struct Opt {
     #[structopt(short, long, value_delimiter = ",", default_value = "math,lambda")]
     benchmarks: Vec<String>,
@@ -174,43 +757,398 @@ pub struct Opt {
     sizes: Vec<usize>,
     #[structopt(short, long, default_value = "out/benchmark.csv")]
     filename: String,
+    /// csv, or jsonl for JSON records with the configuration and run details
+    #[structopt(long, default_value = "csv")]
+    format: ResultsFormat,
     #[structopt(long, default_value = "all")]
     strategy: String,
     #[structopt(long, default_value = "1")]
     samples: usize,
     #[structopt(long, default_value = "60")]
     timeout: f64,
+    /// Abandon a search as out of memory once the process grows by this many
+    /// MB while it runs
+    #[structopt(long)]
+    memory_limit: Option<u64>,
     #[structopt(long)]
     verbose: bool,
+    /// Only bench patterns of these types (linear, nonlinear, ground); all by default
+    #[structopt(long, value_delimiter = ",")]
+    category: Vec<PatternType>,
+    /// Bench the left-hand sides of conditional rules, filtered by their conditions,
+    /// instead of the patterns in patterns.csv
+    #[structopt(long)]
+    conditional: bool,
+    /// With --conditional, search a copy of the e-graph that single-variable
+    /// guards were applied to, so fewer matches are left to check
+    #[structopt(long)]
+    pushdown: bool,
+    /// Values of n for generated suites like adversarial
+    #[structopt(long, value_delimiter = ",", default_value = "16,32,64,128,256")]
+    params: Vec<usize>,
+    /// FPCore files or directories whose cores are added to the math start expressions
+    #[structopt(long, value_delimiter = ",")]
+    fpcore: Vec<String>,
+    /// The start expressions imported from --fpcore, see `Opt::import_fpcore`.
+    #[structopt(skip)]
+    #[serde(skip)]
+    fpcore_exprs: Vec<String>,
+    /// Write a timeline of the run to this file, in the Chrome trace event format
+    #[structopt(long)]
+    trace: Option<String>,
+    /// Only run the i-th of n parts of the searches, numbered from 0, e.g. to
+    /// run them on n machines and put the results together with merge
+    #[structopt(long, default_value = "0/1")]
+    shard: shard::Shard,
+    /// With --shard, give out single searches instead of whole e-graphs, for
+    /// a more even split at the cost of every shard growing the big e-graphs
+    #[structopt(long)]
+    shard_by_pattern: bool,
+    /// Only bench patterns matching one of these: a pattern id, a type, a
+    /// pattern, or re: and a regex on the pattern; all by default
+    #[structopt(long, number_of_values = 1)]
+    pattern: Vec<PatternFilter>,
+    /// Like --pattern, but per bench; all of a bench if it has none.
+    /// Only set by experiment files.
+    #[structopt(skip)]
+    patterns: collections::BTreeMap<String, Vec<PatternFilter>>,
+    #[structopt(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+}
+
+impl Opt {
+    /// A hash of the options that affect the results, so results of
+    /// different configurations can be told apart. Where the results go and
+    /// how verbose the run is aren't part of it.
+    fn config_hash(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap();
+        let options = value.as_object_mut().unwrap();
+        for output in &["filename", "format", "trace", "verbose", "shard"] {
+            options.remove(*output);
+        }
+        format!("{:08x}", fnv1a(value.to_string().bytes()))
+    }
+
+    /// Imports the cores of --fpcore, so the files are read once and not for
+    /// every sample.
+    fn import_fpcore(&mut self) -> Result<(), String> {
+        self.fpcore_exprs.clear();
+        for path in &self.fpcore {
+            let exprs = fpcore::import(path, self.verbose)?;
+            self.fpcore_exprs.extend(exprs);
+        }
+        Ok(())
+    }
+
+    /// Whether a pattern of the bench passes --category, --pattern and the
+    /// per-bench filters.
+    fn selects<L: Language + Display>(
+        &self,
+        bench: &str,
+        id: &str,
+        category: PatternType,
+        pattern: &Pattern<L>,
+    ) -> bool {
+        let pretty = pattern.pretty(usize::MAX);
+        let passes = |filters: &[PatternFilter]| {
+            filters.is_empty() || filters.iter().any(|f| f.matches(id, category, &pretty))
+        };
+        (self.category.is_empty() || self.category.contains(&category))
+            && passes(&self.pattern)
+            && passes(self.patterns.get(bench).map_or(&[], |f| &f[..]))
+    }
+
+    /// Whether the search of the pattern on the bench's e-graph of this size
+    /// is in this shard. Each search is given out by a hash of the bench and
+    /// the size, or with --shard-by-pattern of those and the pattern id, so it
+    /// stays in the same shard whatever else is run.
+    fn owns(&self, bench: &str, node_limit: usize, id: &str) -> bool {
+        if self.shard_by_pattern {
+            self.shard.owns(&format!("{} {} {}", bench, node_limit, id))
+        } else {
+            self.shard.owns(&format!("{} {}", bench, node_limit))
+        }
+    }
+
+    /// The ids of the searches of the bench that pass the filters.
+    fn selected_ids<L: Language + Display, A: Analysis<L>>(
+        &self,
+        bench: &Bench<L, A>,
+    ) -> Vec<String> {
+        bench
+            .searches(self.conditional)
+            .into_iter()
+            .filter(|(id, category, p)| self.selects(&bench.name, id, *category, p))
+            .map(|(id, _, _)| id)
+            .collect()
+    }
+
+    /// Checks that every filter of --pattern selects some search of the
+    /// benches, and every per-bench filter one of its bench, as a filter that
+    /// selects nothing is most likely a typo. Also fails if a bench can't be
+    /// read.
+    fn check_filters(&self) -> Result<(), Vec<String>> {
+        let mut searches = collections::BTreeMap::new();
+        for bench in &self.benchmarks {
+            let mut found = vec![];
+            let visitor = CollectSearches {
+                conditional: self.conditional,
+                found: &mut found,
+            };
+            with_bench(self, bench, visitor)?;
+            searches.insert(bench.as_str(), found);
+        }
+        let selects_any = |filter: &PatternFilter, bench: &str| {
+            searches.get(bench).map_or(false, |found| {
+                found
+                    .iter()
+                    .any(|(id, category, pretty)| filter.matches(id, *category, pretty))
+            })
+        };
+        let mut problems = vec![];
+        for filter in &self.pattern {
+            if !self
+                .benchmarks
+                .iter()
+                .any(|b| selects_any(filter, b.as_str()))
+            {
+                problems.push(format!(
+                    "--pattern {} selects none of the searches of {}",
+                    filter,
+                    self.benchmarks.join(", ")
+                ));
+            }
+        }
+        for (bench, filters) in &self.patterns {
+            for filter in filters.iter().filter(|f| !selects_any(*f, bench.as_str())) {
+                problems.push(format!(
+                    "the filter {} selects none of the searches of {}",
+                    filter, bench
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// The index of the biggest of the bench's e-graphs of these `sizes` that
+    /// has searches in this shard, so the bench can stop growing it after
+    /// that one, or skip it if there is none.
+    fn last_size<L: Language + Display, A: Analysis<L>>(
+        &self,
+        bench: &Bench<L, A>,
+        sizes: &[usize],
+    ) -> Option<usize> {
+        let ids = self.selected_ids(bench);
+        if ids.is_empty() {
+            eprintln!("Skipping {}, none of its patterns are selected", bench.name);
+            return None;
+        }
+        let last = sizes
+            .iter()
+            .rposition(|n| ids.iter().any(|id| self.owns(&bench.name, *n, id)));
+        if last.is_none() {
+            eprintln!(
+                "Skipping {}, none of its searches are in shard {}",
+                bench.name, self.shard
+            );
+        }
+        last
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Summarize result files in the table of compare.py
+    Report(report::ReportOpt),
+    /// Compare the timings of a candidate run against a baseline run
+    CompareRuns(compare::CompareOpt),
+    /// Check that the results of the shards of a run belong together and merge them
+    Merge(shard::MergeOpt),
+    /// Search one pattern on one e-graph in a loop, e.g. to profile it with perf
+    RunOne(run_one::RunOneOpt),
+    /// Run the experiments of a TOML file
+    Run(experiment::RunOpt),
+    /// List the benches, their rules and their patterns with their ids
+    List(list::ListOpt),
+}
+
+/// Something to do with a bench, whatever its language, like running it or
+/// listing its patterns. See `with_bench`.
+pub trait BenchVisitor {
+    /// `generate` builds the e-graphs of suites that aren't grown by their
+    /// rules, see `run_generated_bench`.
+    fn visit<L, A>(self, bench: Bench<L, A>, generate: Option<fn(usize) -> EGraph<L, A>>)
+    where
+        A: Analysis<L> + Default + Clone + Send + Sync,
+        L: Language + FromOp + Sync + Send + Display,
+        <A as egg::Analysis<L>>::Data: Send + Clone,
+        <L as egg::Language>::Operator: Send + Sync;
 }
 
-fn math(opt: &Opt, strategies: &[Strategy], wtr: &mut csv::Writer<std::fs::File>) {
-    run_bench(opt, math::math_bench(), strategies, wtr)
+/// The names of the benches, for `with_bench`.
+pub const BENCHES: &[&str] = &[
+    "math",
+    "lambda",
+    "debruijn",
+    "bool",
+    "tensor",
+    "relational",
+    "halide",
+    "bitvector",
+    "adversarial",
+];
+
+/// Calls the visitor with the bench of this name, with the cores imported
+/// from `--fpcore` added to math. Fails if there is no such bench or its
+/// patterns can't be read.
+pub fn with_bench(opt: &Opt, name: &str, visitor: impl BenchVisitor) -> Result<(), Vec<String>> {
+    match name {
+        "math" => {
+            let mut bench = math::math_bench()?;
+            bench.start_exprs.extend(opt.fpcore_exprs.iter().cloned());
+            visitor.visit(bench, None)
+        }
+        "lambda" => visitor.visit(lambda::lambda_bench()?, None),
+        "debruijn" => visitor.visit(debruijn::debruijn_bench()?, None),
+        "bool" => visitor.visit(bool::bool_bench()?, None),
+        "tensor" => visitor.visit(tensor::tensor_bench()?, None),
+        "relational" => visitor.visit(relational::relational_bench()?, None),
+        "halide" => visitor.visit(halide::halide_bench()?, None),
+        "bitvector" => visitor.visit(bitvector::bitvector_bench()?, None),
+        "adversarial" => {
+            let generate: fn(usize) -> EGraph<_, _> = adversarial::generate;
+            visitor.visit(adversarial::adversarial_bench()?, Some(generate))
+        }
+        _ => {
+            return Err(vec![format!(
+                "unknown bench {}, expected one of {}",
+                name,
+                BENCHES.join(", ")
+            )])
+        }
+    }
+    Ok(())
 }
 
-fn lambda(opt: &Opt, strategies: &[Strategy], wtr: &mut csv::Writer<std::fs::File>) {
-    run_bench(opt, lambda::lambda_bench(), strategies, wtr)
+/// Collects the ids, types and printed patterns of a bench's searches, for
+/// `Opt::check_filters`.
+struct CollectSearches<'a> {
+    conditional: bool,
+    found: &'a mut Vec<(String, PatternType, String)>,
+}
+
+impl BenchVisitor for CollectSearches<'_> {
+    fn visit<L, A>(self, bench: Bench<L, A>, _: Option<fn(usize) -> EGraph<L, A>>)
+    where
+        A: Analysis<L> + Default + Clone + Send + Sync,
+        L: Language + FromOp + Sync + Send + Display,
+        <A as egg::Analysis<L>>::Data: Send + Clone,
+        <L as egg::Language>::Operator: Send + Sync,
+    {
+        for (id, category, pattern) in bench.searches(self.conditional) {
+            self.found.push((id, category, pattern.pretty(usize::MAX)));
+        }
+    }
+}
+
+/// Runs a bench as part of `run`.
+struct RunBench<'a> {
+    opt: &'a Opt,
+    strategies: &'a [Strategy],
+    out: &'a mut Output,
+}
+
+impl BenchVisitor for RunBench<'_> {
+    fn visit<L, A>(self, bench: Bench<L, A>, generate: Option<fn(usize) -> EGraph<L, A>>)
+    where
+        A: Analysis<L> + Default + Clone + Send + Sync,
+        L: Language + FromOp + Sync + Send + Display,
+        <A as egg::Analysis<L>>::Data: Send + Clone,
+        <L as egg::Language>::Operator: Send + Sync,
+    {
+        match generate {
+            Some(generate) => {
+                run_generated_bench(self.opt, bench, generate, self.strategies, self.out)
+            }
+            None => run_bench(self.opt, bench, self.strategies, self.out),
+        }
+    }
 }
 
 fn main() {
-    let start = Instant::now();
     let _ = env_logger::init();
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+    if opt.command.is_none() {
+        if let Err(e) = opt.import_fpcore() {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+    match &opt.command {
+        Some(Command::Report(report_opt)) => report::report(report_opt),
+        Some(Command::CompareRuns(compare_opt)) => compare::compare_runs(compare_opt),
+        Some(Command::Merge(merge_opt)) => shard::merge(merge_opt),
+        Some(Command::RunOne(one_opt)) => run_one::run_one(one_opt),
+        Some(Command::Run(run_opt)) => experiment::run(run_opt),
+        Some(Command::List(list_opt)) => list::list(list_opt),
+        None => run(&opt),
+    }
+}
+
+/// Runs the benches with the options.
+fn run(opt: &Opt) {
+    let start = Instant::now();
     let strategies = match opt.strategy.as_str() {
         "all" => vec![Strategy::GenericJoin, Strategy::EMatch],
         "gj" => vec![Strategy::GenericJoin],
         "em" => vec![Strategy::EMatch],
         _ => panic!("strategy should be one of all, gj, or em"),
     };
-    let out = std::fs::File::create(&opt.filename).unwrap();
-    let mut wtr = csv::Writer::from_writer(out);
-    let mut bench_collection: collections::HashMap<String, fn(_, _, &mut _)> = Default::default();
-    bench_collection.insert("math".into(), math);
-    bench_collection.insert("lambda".into(), lambda);
-    for _ in 0..opt.samples {
+    if let Err(problems) = opt.check_filters() {
+        for p in problems {
+            eprintln!("error: {}", p);
+        }
+        process::exit(1);
+    }
+    let mut env = environment::Environment::current();
+    env.run = Some(environment::RunConfig {
+        config_hash: opt.config_hash(),
+        shard: opt.shard.to_string(),
+    });
+    environment::write(path::Path::new(&opt.filename), &env).unwrap();
+    let mut out = Output {
+        results: Results::create(opt, &env).unwrap(),
+        stats: csv::Writer::from_path(stats::path_for(path::Path::new(&opt.filename))).unwrap(),
+        sample: 0,
+        config_hash: opt.config_hash(),
+        trace: match &opt.trace {
+            Some(path) => trace::Trace::create(path).unwrap(),
+            None => trace::Trace::disabled(),
+        },
+        abandoned: Vec::new(),
+    };
+    for sample in 0..opt.samples {
+        out.sample = sample;
         for bench in &opt.benchmarks {
-            let bench_fn = &bench_collection[&bench.clone()];
-            bench_fn(&opt, &strategies, &mut wtr);
+            let start = Instant::now();
+            let visitor = RunBench {
+                opt,
+                strategies: &strategies,
+                out: &mut out,
+            };
+            if let Err(problems) = with_bench(opt, bench, visitor) {
+                for p in problems {
+                    eprintln!("error: {}", p);
+                }
+                process::exit(1);
+            }
+            let args = json!({ "bench": bench, "sample": sample });
+            out.trace.span("bench", "bench", start, args);
         }
     }
 
@@ -3,10 +3,11 @@ use egg::{define_language, rewrite as rw, Id, Symbol};
 use ordered_float::NotNan;
 use std::cmp::Ordering;
 
-pub fn math_bench() -> Bench<Math, ConstantFold> {
-    Bench {
+pub fn math_bench() -> Result<Bench<Math, ConstantFold>, Vec<String>> {
+    let (rules, conditionals) = rules();
+    Ok(Bench {
         name: "math".into(),
-        start_exprs: vec![
+        start_exprs: [
             "(i (ln x) x)",
             "(i (+ x (cos x)) x)",
             "(i (* (cos x) x) x)",
@@ -14,10 +15,15 @@ pub fn math_bench() -> Bench<Math, ConstantFold> {
             "(d x (- (pow x 3) (* 7 (pow x 2))))",
             "(+ (* y (+ x y)) (- (+ x 2) (+ x x)))",
             "(/ 1 (- (/ (+ 1 (sqrt five)) 2) (/ (- 1 (sqrt five)) 2)))",
-        ],
-        rules: rules(),
-        bench_pats: parse_patterns("math"),
-    }
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rules,
+        bench_pats: parse_patterns("math")?,
+        conditionals,
+        has_data: Some(|data| data.is_some()),
+    })
 }
 
 pub type Constant = NotNan<f64>;
@@ -39,6 +45,13 @@ define_language! {
         "sin" = Sin(Id),
         "cos" = Cos(Id),
 
+        // only produced by the FPCore importer, no rules mention them
+        "exp" = Exp(Id),
+        "tan" = Tan(Id),
+        "atan" = Atan(Id),
+        "cbrt" = Cbrt(Id),
+        "fabs" = Fabs(Id),
+
         Constant(Constant),
         Symbol(Symbol),
     }
@@ -96,108 +109,109 @@ impl Analysis<Math> for ConstantFold {
     }
 }
 
-fn is_const_or_distinct_var(v: &str, w: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
-    let v = v.parse().unwrap();
-    let w = w.parse().unwrap();
-    move |egraph, _, subst| {
-        egraph.find(subst[v]) != egraph.find(subst[w])
-            && (egraph[subst[v]].data.is_some()
-                || egraph[subst[v]]
-                    .nodes
-                    .iter()
-                    .any(|n| matches!(n, Math::Symbol(..))))
-    }
+fn const_class(egraph: &EGraph, id: Id) -> bool {
+    egraph[id].data.is_some()
 }
 
-fn is_const(var: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
-    let var = var.parse().unwrap();
-    move |egraph, _, subst| egraph[subst[var]].data.is_some()
+fn sym_class(egraph: &EGraph, id: Id) -> bool {
+    egraph[id]
+        .nodes
+        .iter()
+        .any(|n| matches!(n, Math::Symbol(..)))
 }
 
-fn is_sym(var: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
-    let var = var.parse().unwrap();
-    move |egraph, _, subst| {
-        egraph[subst[var]]
-            .nodes
-            .iter()
-            .any(|n| matches!(n, Math::Symbol(..)))
+fn not_zero_class(egraph: &EGraph, id: Id) -> bool {
+    if let Some(n) = egraph[id].data {
+        *n != 0.0
+    } else {
+        true
     }
 }
 
-fn is_not_zero(var: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
-    let var = var.parse().unwrap();
+fn is_const_or_distinct_var(v: &str, w: &str) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let v = v.parse().unwrap();
+    let w = w.parse().unwrap();
     move |egraph, _, subst| {
-        if let Some(n) = egraph[subst[var]].data {
-            *n != 0.0
-        } else {
-            true
-        }
+        egraph.find(subst[v]) != egraph.find(subst[w])
+            && (const_class(egraph, subst[v]) || sym_class(egraph, subst[v]))
     }
 }
 
+/// The rules, and the searchers of the conditional ones for `--conditional`.
 #[rustfmt::skip]
-pub fn rules() -> Vec<Rewrite<Math, ConstantFold>> { vec![
-    rw!("comm-add";  "(+ ?a ?b)"        => "(+ ?b ?a)"),
-    rw!("comm-mul";  "(* ?a ?b)"        => "(* ?b ?a)"),
-    rw!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
-    rw!("assoc-mul"; "(* ?a (* ?b ?c))" => "(* (* ?a ?b) ?c)"),
-
-    rw!("sub-canon"; "(- ?a ?b)" => "(+ ?a (* -1 ?b))"),
-    rw!("div-canon"; "(/ ?a ?b)" => "(* ?a (pow ?b -1))" if is_not_zero("?b")),
-    // rw!("canon-sub"; "(+ ?a (* -1 ?b))"   => "(- ?a ?b)"),
-    // rw!("canon-div"; "(* ?a (pow ?b -1))" => "(/ ?a ?b)" if is_not_zero("?b")),
-
-    rw!("zero-add"; "(+ ?a 0)" => "?a"),
-    rw!("zero-mul"; "(* ?a 0)" => "0"),
-    rw!("one-mul";  "(* ?a 1)" => "?a"),
-
-    rw!("add-zero"; "?a" => "(+ ?a 0)"),
-    rw!("mul-one";  "?a" => "(* ?a 1)"),
-
-    rw!("cancel-sub"; "(- ?a ?a)" => "0"),
-    rw!("cancel-div"; "(/ ?a ?a)" => "1" if is_not_zero("?a")),
-
-    rw!("distribute"; "(* ?a (+ ?b ?c))"        => "(+ (* ?a ?b) (* ?a ?c))"),
-    rw!("factor"    ; "(+ (* ?a ?b) (* ?a ?c))" => "(* ?a (+ ?b ?c))"),
-
-    rw!("pow-mul"; "(* (pow ?a ?b) (pow ?a ?c))" => "(pow ?a (+ ?b ?c))"),
-    rw!("pow0"; "(pow ?x 0)" => "1"
-        if is_not_zero("?x")),
-    rw!("pow1"; "(pow ?x 1)" => "?x"),
-    rw!("pow2"; "(pow ?x 2)" => "(* ?x ?x)"),
-    rw!("pow-recip"; "(pow ?x -1)" => "(/ 1 ?x)"
-        if is_not_zero("?x")),
-    rw!("recip-mul-div"; "(* ?x (/ 1 ?x))" => "1" if is_not_zero("?x")),
-
-    rw!("d-variable"; "(d ?x ?x)" => "1" if is_sym("?x")),
-    rw!("d-constant"; "(d ?x ?c)" => "0" if is_sym("?x") if is_const_or_distinct_var("?c", "?x")),
-
-    rw!("d-add"; "(d ?x (+ ?a ?b))" => "(+ (d ?x ?a) (d ?x ?b))"),
-    rw!("d-mul"; "(d ?x (* ?a ?b))" => "(+ (* ?a (d ?x ?b)) (* ?b (d ?x ?a)))"),
-
-    rw!("d-sin"; "(d ?x (sin ?x))" => "(cos ?x)"),
-    rw!("d-cos"; "(d ?x (cos ?x))" => "(* -1 (sin ?x))"),
-
-    rw!("d-ln"; "(d ?x (ln ?x))" => "(/ 1 ?x)" if is_not_zero("?x")),
-
-    rw!("d-power";
-        "(d ?x (pow ?f ?g))" =>
-        "(* (pow ?f ?g)
-            (+ (* (d ?x ?f)
-                  (/ ?g ?f))
-               (* (d ?x ?g)
-                  (ln ?f))))"
-        if is_not_zero("?f")
-        if is_not_zero("?g")
-    ),
-
-    rw!("i-one"; "(i 1 ?x)" => "?x"),
-    rw!("i-power-const"; "(i (pow ?x ?c) ?x)" =>
-        "(/ (pow ?x (+ ?c 1)) (+ ?c 1))" if is_const("?c")),
-    rw!("i-cos"; "(i (cos ?x) ?x)" => "(sin ?x)"),
-    rw!("i-sin"; "(i (sin ?x) ?x)" => "(* -1 (cos ?x))"),
-    rw!("i-sum"; "(i (+ ?f ?g) ?x)" => "(+ (i ?f ?x) (i ?g ?x))"),
-    rw!("i-dif"; "(i (- ?f ?g) ?x)" => "(- (i ?f ?x) (i ?g ?x))"),
-    rw!("i-parts"; "(i (* ?a ?b) ?x)" =>
-        "(- (* ?a (i ?b ?x)) (i (* (d ?x ?a) (i ?b ?x)) ?x))"),
-]}
+pub fn rules() -> (Vec<Rewrite<Math, ConstantFold>>, Vec<ConditionalSearcher<Math, ConstantFold>>) {
+    let mut c = Conditionals::default();
+    let rules = vec![
+        rw!("comm-add";  "(+ ?a ?b)"        => "(+ ?b ?a)"),
+        rw!("comm-mul";  "(* ?a ?b)"        => "(* ?b ?a)"),
+        rw!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+        rw!("assoc-mul"; "(* ?a (* ?b ?c))" => "(* (* ?a ?b) ?c)"),
+
+        rw!("sub-canon"; "(- ?a ?b)" => "(+ ?a (* -1 ?b))"),
+        c.add(ConditionalSearcher::new("div-canon", "(/ ?a ?b)").guard("?b", not_zero_class))
+            .rewrite("(* ?a (pow ?b -1))"),
+        // rw!("canon-sub"; "(+ ?a (* -1 ?b))"   => "(- ?a ?b)"),
+        // rw!("canon-div"; "(* ?a (pow ?b -1))" => "(/ ?a ?b)" if is_not_zero("?b")),
+
+        rw!("zero-add"; "(+ ?a 0)" => "?a"),
+        rw!("zero-mul"; "(* ?a 0)" => "0"),
+        rw!("one-mul";  "(* ?a 1)" => "?a"),
+
+        rw!("add-zero"; "?a" => "(+ ?a 0)"),
+        rw!("mul-one";  "?a" => "(* ?a 1)"),
+
+        rw!("cancel-sub"; "(- ?a ?a)" => "0"),
+        c.add(ConditionalSearcher::new("cancel-div", "(/ ?a ?a)").guard("?a", not_zero_class))
+            .rewrite("1"),
+
+        rw!("distribute"; "(* ?a (+ ?b ?c))"        => "(+ (* ?a ?b) (* ?a ?c))"),
+        rw!("factor"    ; "(+ (* ?a ?b) (* ?a ?c))" => "(* ?a (+ ?b ?c))"),
+
+        rw!("pow-mul"; "(* (pow ?a ?b) (pow ?a ?c))" => "(pow ?a (+ ?b ?c))"),
+        c.add(ConditionalSearcher::new("pow0", "(pow ?x 0)").guard("?x", not_zero_class))
+            .rewrite("1"),
+        rw!("pow1"; "(pow ?x 1)" => "?x"),
+        rw!("pow2"; "(pow ?x 2)" => "(* ?x ?x)"),
+        c.add(ConditionalSearcher::new("pow-recip", "(pow ?x -1)").guard("?x", not_zero_class))
+            .rewrite("(/ 1 ?x)"),
+        c.add(ConditionalSearcher::new("recip-mul-div", "(* ?x (/ 1 ?x))").guard("?x", not_zero_class))
+            .rewrite("1"),
+
+        c.add(ConditionalSearcher::new("d-variable", "(d ?x ?x)").guard("?x", sym_class))
+            .rewrite("1"),
+        c.add(ConditionalSearcher::new("d-constant", "(d ?x ?c)")
+            .guard("?x", sym_class)
+            .condition(is_const_or_distinct_var("?c", "?x")))
+            .rewrite("0"),
+
+        rw!("d-add"; "(d ?x (+ ?a ?b))" => "(+ (d ?x ?a) (d ?x ?b))"),
+        rw!("d-mul"; "(d ?x (* ?a ?b))" => "(+ (* ?a (d ?x ?b)) (* ?b (d ?x ?a)))"),
+
+        rw!("d-sin"; "(d ?x (sin ?x))" => "(cos ?x)"),
+        rw!("d-cos"; "(d ?x (cos ?x))" => "(* -1 (sin ?x))"),
+
+        c.add(ConditionalSearcher::new("d-ln", "(d ?x (ln ?x))").guard("?x", not_zero_class))
+            .rewrite("(/ 1 ?x)"),
+
+        c.add(ConditionalSearcher::new("d-power", "(d ?x (pow ?f ?g))")
+            .guard("?f", not_zero_class)
+            .guard("?g", not_zero_class))
+            .rewrite(
+            "(* (pow ?f ?g)
+                (+ (* (d ?x ?f)
+                      (/ ?g ?f))
+                   (* (d ?x ?g)
+                      (ln ?f))))"),
+
+        rw!("i-one"; "(i 1 ?x)" => "?x"),
+        c.add(ConditionalSearcher::new("i-power-const", "(i (pow ?x ?c) ?x)").guard("?c", const_class))
+            .rewrite("(/ (pow ?x (+ ?c 1)) (+ ?c 1))"),
+        rw!("i-cos"; "(i (cos ?x) ?x)" => "(sin ?x)"),
+        rw!("i-sin"; "(i (sin ?x) ?x)" => "(* -1 (cos ?x))"),
+        rw!("i-sum"; "(i (+ ?f ?g) ?x)" => "(+ (i ?f ?x) (i ?g ?x))"),
+        rw!("i-dif"; "(i (- ?f ?g) ?x)" => "(- (i ?f ?x) (i ?g ?x))"),
+        rw!("i-parts"; "(i (* ?a ?b) ?x)" =>
+            "(- (* ?a (i ?b ?x)) (i (* (d ?x ?a) (i ?b ?x)) ?x))"),
+    ];
+    (rules, c.into_searchers())
+}
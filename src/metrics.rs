@@ -0,0 +1,331 @@
+//! Structural metrics of patterns, to relate the shape of a query to how the
+//! strategies perform on it.
+
+use crate::lp;
+use crate::patterns::fnv1a;
+use egg::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+/// The query hypergraph of a pattern as GJ sees it. There is a vertex for
+/// every pattern variable and for the e-class of every e-node in the pattern,
+/// and an edge (atom) for every e-node, over its own class and its children.
+#[derive(Debug, Clone)]
+pub struct Hypergraph<L> {
+    pub vertices: usize,
+    /// Each atom's e-node and the sorted, deduplicated vertices it covers.
+    pub atoms: Vec<(L, Vec<usize>)>,
+}
+
+impl<L: Language> Hypergraph<L> {
+    pub fn of(pat: &Pattern<L>) -> Self {
+        let ast = pat.ast.as_ref();
+        let mut var_vertex: HashMap<Var, usize> = HashMap::new();
+        let mut vertex = Vec::with_capacity(ast.len());
+        let mut vertices = 0;
+        for node in ast {
+            let v = match node {
+                ENodeOrVar::Var(var) => *var_vertex.entry(*var).or_insert(vertices),
+                ENodeOrVar::ENode(_) => vertices,
+            };
+            if v == vertices {
+                vertices += 1;
+            }
+            vertex.push(v);
+        }
+
+        let atoms = ast
+            .iter()
+            .enumerate()
+            .filter_map(|(i, node)| match node {
+                ENodeOrVar::ENode(n) => {
+                    let mut edge = vec![vertex[i]];
+                    edge.extend(n.children().iter().map(|c| vertex[usize::from(*c)]));
+                    edge.sort_unstable();
+                    edge.dedup();
+                    Some((n.clone(), edge))
+                }
+                ENodeOrVar::Var(_) => None,
+            })
+            .collect();
+        Hypergraph { vertices, atoms }
+    }
+
+    /// The sum of the edge sizes.
+    pub fn size(&self) -> usize {
+        self.atoms.iter().map(|(_, e)| e.len()).sum()
+    }
+
+    /// Whether the hypergraph is alpha-acyclic, by GYO reduction: repeatedly
+    /// drop vertices that are in only one edge and edges that are contained in
+    /// another, and check that nothing is left.
+    pub fn is_acyclic(&self) -> bool {
+        let mut edges: Vec<BTreeSet<usize>> = self
+            .atoms
+            .iter()
+            .map(|(_, e)| e.iter().copied().collect())
+            .collect();
+        loop {
+            let before: usize = edges.len() + edges.iter().map(|e| e.len()).sum::<usize>();
+            for v in 0..self.vertices {
+                if edges.iter().filter(|e| e.contains(&v)).count() == 1 {
+                    edges.iter_mut().for_each(|e| {
+                        e.remove(&v);
+                    });
+                }
+            }
+            let mut i = 0;
+            while i < edges.len() {
+                let contained = edges.iter().enumerate().any(|(j, other)| {
+                    j != i && edges[i].is_subset(other) && (edges[i] != *other || j < i)
+                });
+                if edges[i].is_empty() || contained {
+                    edges.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+            let after: usize = edges.len() + edges.iter().map(|e| e.len()).sum::<usize>();
+            if edges.is_empty() {
+                return true;
+            }
+            if after == before {
+                return false;
+            }
+        }
+    }
+
+    /// The minimum of `sum(weights[e] * x[e])` over the fractional edge covers
+    /// `x`, i.e. the `x >= 0` where the edges around each vertex add up to at
+    /// least one. Solved as the dual LP, a fractional vertex packing.
+    /// The weights have to be non-negative.
+    pub fn min_edge_cover(&self, weights: &[f64]) -> f64 {
+        assert_eq!(weights.len(), self.atoms.len());
+        let covered: BTreeSet<usize> = self.atoms.iter().flat_map(|(_, e)| e).copied().collect();
+        let column: HashMap<usize, usize> =
+            covered.iter().enumerate().map(|(j, v)| (*v, j)).collect();
+        let rows: Vec<Vec<f64>> = self
+            .atoms
+            .iter()
+            .map(|(_, edge)| {
+                let mut row = vec![0.0; covered.len()];
+                edge.iter().for_each(|v| row[column[v]] = 1.0);
+                row
+            })
+            .collect();
+        lp::maximize(&vec![1.0; covered.len()], &rows, weights)
+    }
+
+    /// The AGM bound on the number of results of this query over relations
+    /// with the given sizes: the minimum of `prod(size[e] ^ x[e])` over the
+    /// fractional edge covers `x`, found as the weighted cover with weights
+    /// `log2(size[e])`.
+    pub fn agm_bound(&self, sizes: &RelationSizes<L>) -> f64 {
+        let sizes: Vec<usize> = self.atoms.iter().map(|(n, _)| sizes.get(n)).collect();
+        if sizes.contains(&0) {
+            return 0.0;
+        }
+        let weights: Vec<f64> = sizes.iter().map(|&s| (s as f64).log2()).collect();
+        self.min_edge_cover(&weights).exp2()
+    }
+}
+
+/// How many e-nodes of each operator an e-graph has, i.e. the size of the
+/// relation that each atom of a pattern is joined over.
+#[derive(Debug, Clone)]
+pub struct RelationSizes<L>(HashMap<L, usize>);
+
+impl<L: Language> RelationSizes<L> {
+    pub fn of<A: Analysis<L>>(egraph: &EGraph<L, A>) -> Self {
+        let mut sizes = HashMap::new();
+        for class in egraph.classes() {
+            for node in &class.nodes {
+                *sizes.entry(operator(node)).or_default() += 1;
+            }
+        }
+        RelationSizes(sizes)
+    }
+
+    /// The number of e-nodes that `node` matches, ignoring its children.
+    pub fn get(&self, node: &L) -> usize {
+        self.0.get(&operator(node)).copied().unwrap_or(0)
+    }
+}
+
+impl<L: Language + Display> RelationSizes<L> {
+    /// A hash of the e-node count of every operator. It doesn't depend on
+    /// e-class ids, so the same e-graph built by two runs gets the same
+    /// fingerprint and their results can be matched on it.
+    pub fn fingerprint(&self) -> String {
+        let mut counts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(op, n)| format!("{}:{}", op, n))
+            .collect();
+        counts.sort();
+        format!("{:08x}", fnv1a(counts.join("\n").bytes()))
+    }
+}
+
+/// `node` with all children set to 0, so e-nodes that `matches` each other
+/// are equal.
+fn operator<L: Language>(node: &L) -> L {
+    let mut node = node.clone();
+    node.children_mut()
+        .iter_mut()
+        .for_each(|c| *c = Id::from(0));
+    node
+}
+
+/// Numbers describing the shape of a pattern, recorded next to its timings.
+#[derive(Debug, Clone)]
+pub struct PatternMetrics {
+    pub atoms: usize,
+    /// Vertices of the query hypergraph: pattern variables plus one per e-node.
+    pub query_vars: usize,
+    pub hypergraph_size: usize,
+    pub distinct_vars: usize,
+    /// Pattern variables that occur more than once.
+    pub repeated_vars: usize,
+    pub acyclic: bool,
+    /// Nesting depth of the pattern, where a variable has depth 0.
+    pub depth: usize,
+    /// The fractional edge cover number, the exponent of the AGM bound when
+    /// every relation has the same size.
+    pub edge_cover: f64,
+}
+
+impl PatternMetrics {
+    pub fn of<L: Language>(pat: &Pattern<L>) -> Self {
+        let ast = pat.ast.as_ref();
+        let mut var_counts: HashMap<Var, usize> = HashMap::new();
+        let mut depth = vec![0; ast.len()];
+        for (i, node) in ast.iter().enumerate() {
+            match node {
+                ENodeOrVar::Var(v) => *var_counts.entry(*v).or_default() += 1,
+                ENodeOrVar::ENode(n) => {
+                    depth[i] = 1 + n
+                        .children()
+                        .iter()
+                        .map(|c| depth[usize::from(*c)])
+                        .max()
+                        .unwrap_or(0)
+                }
+            }
+        }
+
+        let hypergraph = Hypergraph::of(pat);
+        PatternMetrics {
+            atoms: hypergraph.atoms.len(),
+            query_vars: hypergraph.vertices,
+            hypergraph_size: hypergraph.size(),
+            distinct_vars: var_counts.len(),
+            repeated_vars: var_counts.values().filter(|&&n| n > 1).count(),
+            acyclic: hypergraph.is_acyclic(),
+            depth: depth.last().copied().unwrap_or(0),
+            edge_cover: hypergraph.min_edge_cover(&vec![1.0; hypergraph.atoms.len()]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    /// A hypergraph with an atom `op` over each of the given vertices.
+    fn hypergraph(vertices: usize, atoms: &[(&str, &[usize])]) -> Hypergraph<SymbolLang> {
+        Hypergraph {
+            vertices,
+            atoms: atoms
+                .iter()
+                .map(|(op, e)| (SymbolLang::leaf(*op), e.to_vec()))
+                .collect(),
+        }
+    }
+
+    /// The sizes of the relations of the atoms of `h`, by operator.
+    fn sizes(h: &Hypergraph<SymbolLang>, sizes: &[(&str, usize)]) -> RelationSizes<SymbolLang> {
+        let size = |n: &SymbolLang| sizes.iter().find(|(op, _)| n.op.as_str() == *op);
+        RelationSizes(
+            h.atoms
+                .iter()
+                .filter_map(|(n, _)| Some((operator(n), size(n)?.1)))
+                .collect(),
+        )
+    }
+
+    fn edge_cover(h: &Hypergraph<SymbolLang>) -> f64 {
+        h.min_edge_cover(&vec![1.0; h.atoms.len()])
+    }
+
+    fn pattern(s: &str) -> Pattern<SymbolLang> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn triangle() {
+        let h = hypergraph(3, &[("r", &[0, 1]), ("s", &[1, 2]), ("t", &[0, 2])]);
+        assert!(close(edge_cover(&h), 1.5));
+        let n = 1000;
+        let bound = h.agm_bound(&sizes(&h, &[("r", n), ("s", n), ("t", n)]));
+        assert!(close(bound.log2(), 1.5 * (n as f64).log2()));
+        assert!(!h.is_acyclic());
+    }
+
+    #[test]
+    fn path_and_star() {
+        // the covers are integral: the two ends of the path, every edge of the star
+        let path = hypergraph(4, &[("r", &[0, 1]), ("s", &[1, 2]), ("t", &[2, 3])]);
+        assert!(close(edge_cover(&path), 2.0));
+        assert!(path.is_acyclic());
+        let star = hypergraph(4, &[("r", &[0, 1]), ("s", &[0, 2]), ("t", &[0, 3])]);
+        assert!(close(edge_cover(&star), 3.0));
+        assert!(star.is_acyclic());
+        let bound = star.agm_bound(&sizes(&star, &[("r", 2), ("s", 4), ("t", 8)]));
+        assert!(close(bound, 64.0));
+    }
+
+    #[test]
+    fn single_atom() {
+        let metrics = PatternMetrics::of(&pattern("(f ?x ?y)"));
+        assert_eq!(metrics.atoms, 1);
+        assert_eq!(metrics.query_vars, 3);
+        assert_eq!(metrics.distinct_vars, 2);
+        assert_eq!(metrics.repeated_vars, 0);
+        assert_eq!(metrics.depth, 1);
+        assert!(metrics.acyclic);
+        assert!(close(metrics.edge_cover, 1.0));
+        let h = Hypergraph::of(&pattern("(f ?x ?y)"));
+        assert!(close(h.agm_bound(&sizes(&h, &[("f", 100)])), 100.0));
+    }
+
+    #[test]
+    fn ground() {
+        let h = Hypergraph::of(&pattern("(f a b)"));
+        assert_eq!(h.atoms.len(), 3);
+        assert!(h.is_acyclic());
+        assert!(close(edge_cover(&h), 1.0));
+        // each constant is a single e-node, so the bound is the size of f
+        let bound = h.agm_bound(&sizes(&h, &[("f", 50), ("a", 1), ("b", 1)]));
+        assert!(close(bound, 50.0));
+        // no e-node for a constant means no results
+        assert_eq!(h.agm_bound(&sizes(&h, &[("f", 50), ("a", 1)])), 0.0);
+    }
+
+    #[test]
+    fn acyclic_and_cyclic_patterns() {
+        assert!(Hypergraph::of(&pattern("(f ?x ?x)")).is_acyclic());
+        assert!(Hypergraph::of(&pattern("(f (g ?x) (h ?y))")).is_acyclic());
+        // ?x closes the cycle f - g - ?x - h - f
+        let h = Hypergraph::of(&pattern("(f (g ?x) (h ?x))"));
+        assert!(!h.is_acyclic());
+        assert_eq!(
+            PatternMetrics::of(&pattern("(f (g ?x) (h ?x))")).repeated_vars,
+            1
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! Reading the benchmark patterns from patterns.csv.
+//!
+//! Every row has the columns `bench, const, type, pattern`. Fields are
+//! trimmed, and a pattern with commas has to be quoted. Blank lines and lines
+//! starting with `#` are ignored.
+
+use egg::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display};
+use std::mem;
+use std::str::FromStr;
+
+pub const PATTERNS_FILE: &str = "patterns.csv";
+
+/// What kind of join a pattern is, as declared in the `type` column of
+/// patterns.csv.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternType {
+    /// Every variable occurs once and there are no constants.
+    Linear,
+    /// Some variable occurs more than once, so matching has to check equalities.
+    NonLinear,
+    /// At least one leaf is a constant that anchors the match.
+    Ground,
+}
+
+impl PatternType {
+    /// Classifies a pattern and counts its constant leaves.
+    pub fn of<L: Language>(pat: &Pattern<L>) -> (PatternType, usize) {
+        let mut var_counts: HashMap<Var, usize> = Default::default();
+        let mut consts = 0;
+        for node in pat.ast.as_ref() {
+            match node {
+                ENodeOrVar::Var(v) => *var_counts.entry(*v).or_default() += 1,
+                ENodeOrVar::ENode(n) if n.is_leaf() => consts += 1,
+                ENodeOrVar::ENode(_) => (),
+            }
+        }
+        let ty = if consts > 0 {
+            PatternType::Ground
+        } else if var_counts.values().any(|&n| n > 1) {
+            PatternType::NonLinear
+        } else {
+            PatternType::Linear
+        };
+        (ty, consts)
+    }
+}
+
+impl FromStr for PatternType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(PatternType::Linear),
+            "nonlinear" => Ok(PatternType::NonLinear),
+            "ground" => Ok(PatternType::Ground),
+            _ => Err(format!(
+                "pattern type should be one of linear, nonlinear, or ground, got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for PatternType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            PatternType::Linear => "linear",
+            PatternType::NonLinear => "nonlinear",
+            PatternType::Ground => "ground",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Selects patterns for `--pattern`: a pattern id like `math-0c1f2a3b`, a
+/// pattern type, a pattern as the results print it, or with a `re:` prefix a
+/// regex on that.
+#[derive(Clone, Debug)]
+pub struct PatternFilter {
+    text: String,
+    category: Option<PatternType>,
+    /// Only for filters with the `re:` prefix.
+    regex: Option<Regex>,
+}
+
+impl PatternFilter {
+    pub fn matches(&self, id: &str, category: PatternType, pretty: &str) -> bool {
+        let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        match (&self.regex, self.category) {
+            (Some(regex), _) => regex.is_match(pretty),
+            (None, Some(c)) => c == category,
+            (None, None) => self.text == id || normalize(&self.text) == normalize(pretty),
+        }
+    }
+}
+
+impl FromStr for PatternFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regex = match s.strip_prefix("re:") {
+            Some(re) => Some(Regex::new(re).map_err(|e| format!("invalid regex {}: {}", re, e))?),
+            None => None,
+        };
+        Ok(PatternFilter {
+            text: s.to_string(),
+            category: s.parse().ok(),
+            regex,
+        })
+    }
+}
+
+impl Display for PatternFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Serialize for PatternFilter {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchPattern<L> {
+    /// Derived from the bench name and the pattern text, so it stays the same
+    /// when rows are added or reordered and results can be joined across runs.
+    pub id: String,
+    pub pattern: Pattern<L>,
+    pub category: PatternType,
+    pub consts: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Row {
+    bench: String,
+    #[serde(rename = "const")]
+    consts: usize,
+    #[serde(rename = "type")]
+    category: String,
+    pattern: String,
+}
+
+/// The patterns of `bench_name` in patterns.csv, or every problem with the file.
+pub fn parse_patterns<L: Language + FromOp>(
+    bench_name: &str,
+) -> Result<Vec<BenchPattern<L>>, Vec<String>> {
+    let text = std::fs::read_to_string(PATTERNS_FILE)
+        .map_err(|e| vec![format!("cannot read {}: {}", PATTERNS_FILE, e)])?;
+    read_patterns(PATTERNS_FILE, &text, bench_name)
+}
+
+pub fn read_patterns<L: Language + FromOp>(
+    path: &str,
+    text: &str,
+    bench_name: &str,
+) -> Result<Vec<BenchPattern<L>>, Vec<String>> {
+    let mut reader = reader(text);
+    let headers = reader.headers().map(|h| h.clone()).unwrap_or_default();
+    let expected = ["bench", "const", "type", "pattern"];
+    if !headers.iter().eq(expected.iter().copied()) {
+        let line = headers.position().map_or(1, |p| p.line());
+        return Err(vec![format!(
+            "{}:{}:1: expected the header bench, const, type, pattern",
+            path, line
+        )]);
+    }
+
+    let mut errors = vec![];
+    let mut benches = BTreeSet::new();
+    let mut seen: HashMap<String, u64> = HashMap::new();
+    let mut pats = vec![];
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                errors.push(format!("{}:{}:1: {}", path, line, e));
+                continue;
+            }
+        };
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let pos = record.position().expect("read records have a position");
+        let line = pos.line();
+        let raw = text[pos.byte() as usize..].lines().next().unwrap_or("");
+        let at = |col: usize| format!("{}:{}:{}", path, line, col + 1);
+        if record.len() != headers.len() {
+            errors.push(format!(
+                "{}: expected {} fields, found {}",
+                at(raw.len()),
+                headers.len(),
+                record.len()
+            ));
+            continue;
+        }
+        let cols = offsets(raw, &record);
+        let row: Row = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                let (col, msg) = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => {
+                        let col = err.field().map_or(0, |f| cols[f as usize]);
+                        (col, err.kind().to_string())
+                    }
+                    _ => (0, e.to_string()),
+                };
+                errors.push(format!("{}: {}", at(col), msg));
+                continue;
+            }
+        };
+        benches.insert(row.bench.clone());
+        if row.bench != bench_name {
+            continue;
+        }
+        let declared: PatternType = match row.category.parse() {
+            Ok(ty) => ty,
+            Err(e) => {
+                errors.push(format!("{}: {}", at(cols[2]), e));
+                continue;
+            }
+        };
+
+        let pattern_col = cols[3];
+        if let Err((offset, msg)) = check_operators::<L>(&row.pattern) {
+            errors.push(format!("{}: {}", at(pattern_col + offset), msg));
+            continue;
+        }
+        let pattern: Pattern<L> = match row.pattern.parse() {
+            Ok(p) => p,
+            Err(e) => {
+                errors.push(format!("{}: invalid pattern: {}", at(pattern_col), e));
+                continue;
+            }
+        };
+
+        let (category, consts) = PatternType::of(&pattern);
+        if (category, consts) != (declared, row.consts) {
+            errors.push(format!(
+                "{}: pattern has {} constants and type {}, but the row says {} and {}",
+                at(pattern_col),
+                consts,
+                category,
+                row.consts,
+                declared
+            ));
+            continue;
+        }
+
+        let normalized = row.pattern.split_whitespace().collect::<Vec<_>>().join(" ");
+        if let Some(first) = seen.get(&normalized) {
+            eprintln!(
+                "warning: {}: skipping duplicate pattern {}, first seen on line {}",
+                at(pattern_col),
+                normalized,
+                first
+            );
+            continue;
+        }
+        seen.insert(normalized.clone(), line);
+
+        pats.push(BenchPattern {
+            id: pattern_id(bench_name, &normalized),
+            pattern,
+            category,
+            consts,
+        });
+    }
+
+    if errors.is_empty() && pats.is_empty() {
+        errors.push(format!(
+            "{}: no patterns for bench {}, the file has {}",
+            path,
+            bench_name,
+            benches.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    if errors.is_empty() {
+        Ok(pats)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The `type` column of every row of patterns.csv, keyed by the pattern with
+/// single spaces as the results print it. For results that predate the
+/// `category` column; rows are not checked, unlike `read_patterns`.
+pub fn declared_types(text: &str) -> HashMap<String, String> {
+    reader(text)
+        .into_records()
+        .filter_map(Result::ok)
+        .filter(|record| record.len() == 4)
+        .map(|record| {
+            let pattern = record[3].split_whitespace().collect::<Vec<_>>();
+            (pattern.join(" "), record[2].to_string())
+        })
+        .collect()
+}
+
+/// A reader of patterns.csv that trims the fields and skips comments.
+/// Rows may have the wrong number of fields, so `read_patterns` can say where.
+fn reader(text: &str) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .comment(Some(b'#'))
+        .flexible(true)
+        .from_reader(text.as_bytes())
+}
+
+/// The byte offset in its line `raw` of each field of `record`, found by
+/// looking for the fields in order. A field the search can't find, like a
+/// quoted one with escaped quotes, gets the offset where the search started.
+fn offsets(raw: &str, record: &csv::StringRecord) -> Vec<usize> {
+    let mut offsets = vec![];
+    let mut start = 0;
+    for field in record {
+        let offset = raw[start..].find(field).map_or(start, |i| start + i);
+        offsets.push(offset);
+        start = offset + field.len();
+    }
+    offsets
+}
+
+/// Checks that every operator of the s-expression `pat` is in `L` with that
+/// many children. On failure, returns the byte offset of the operator.
+fn check_operators<L: FromOp>(pat: &str) -> Result<(), (usize, String)> {
+    // A `Symbol` variant takes any leaf, so a leaf that only parses to the
+    // variant this does is no constant of the language.
+    let symbol = L::from_op_str("\u{0}", vec![])
+        .ok()
+        .map(|n| mem::discriminant(&n));
+    // operator, its offset, and the number of children seen so far
+    let mut stack: Vec<(&str, usize, usize)> = vec![];
+    let mut chars = pat.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => {
+                while matches!(chars.peek(), Some((_, c)) if c.is_whitespace()) {
+                    chars.next();
+                }
+                let start = chars.peek().map_or(pat.len(), |(j, _)| *j);
+                let mut end = start;
+                while let Some((j, c)) = chars.peek() {
+                    if c.is_whitespace() || *c == '(' || *c == ')' {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                if let Some(parent) = stack.last_mut() {
+                    parent.2 += 1;
+                }
+                stack.push((&pat[start..end], start, 0));
+            }
+            ')' => {
+                let (op, offset, arity) = stack
+                    .pop()
+                    .ok_or_else(|| (i, "unbalanced parentheses".to_string()))?;
+                let children = (0..arity).map(Id::from).collect();
+                if L::from_op_str(op, children).is_err() {
+                    return Err((
+                        offset,
+                        format!(
+                            "no operator {} with {} children in this language",
+                            op, arity
+                        ),
+                    ));
+                }
+            }
+            c if c.is_whitespace() => (),
+            _ => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, c)) = chars.peek() {
+                    if c.is_whitespace() || *c == '(' || *c == ')' {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                let leaf = &pat[i..end];
+                let constant = L::from_op_str(leaf, vec![])
+                    .map_or(false, |n| Some(mem::discriminant(&n)) != symbol);
+                if !leaf.starts_with('?') && !constant {
+                    return Err((i, format!("no constant {} in this language", leaf)));
+                }
+                if let Some(parent) = stack.last_mut() {
+                    parent.2 += 1;
+                }
+            }
+        }
+    }
+    match stack.last() {
+        Some((_, offset, _)) => Err((*offset, "unbalanced parentheses".to_string())),
+        None => Ok(()),
+    }
+}
+
+/// The pattern's bench name and a hash of the pattern.
+fn pattern_id(bench_name: &str, pattern: &str) -> String {
+    let bytes = bench_name.bytes().chain(Some(0)).chain(pattern.bytes());
+    format!("{}-{:08x}", bench_name, fnv1a(bytes))
+}
+
+/// A 32-bit FNV-1a hash, which unlike `DefaultHasher` is the same across
+/// Rust versions.
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in bytes {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Math;
+
+    /// The errors `read_patterns` reports for these rows of math patterns.
+    fn errors(rows: &[&str]) -> Vec<String> {
+        let text = format!("bench, const, type, pattern\n{}\n", rows.join("\n"));
+        match read_patterns::<Math>("test.csv", &text, "math") {
+            Ok(pats) => panic!("read {} patterns", pats.len()),
+            Err(errors) => errors,
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let pretty = "(+ ?a (* ?b ?c))";
+        let selects = |filter: &str| {
+            let filter: PatternFilter = filter.parse().unwrap();
+            filter.matches("math-0c1f2a3b", PatternType::Linear, pretty)
+        };
+        assert!(selects("math-0c1f2a3b"));
+        assert!(selects("linear"));
+        assert!(!selects("ground"));
+        assert!(selects("(+  ?a (* ?b ?c))"));
+        assert!(selects(r"re:\(\* \?b"));
+        // only filters with the prefix are regexes
+        assert!(!selects(r"\(\* \?b"));
+        assert!(!selects("re:math"));
+        assert!("re:(+ ?a".parse::<PatternFilter>().is_err());
+    }
+
+    #[test]
+    fn fields_with_their_offsets() {
+        let raw = "math, 0,  linear ,\"(+ ?a, ?b)\" ";
+        let record = reader(raw).into_records().next().unwrap().unwrap();
+        assert_eq!(&record[3], "(+ ?a, ?b)");
+        assert_eq!(offsets(raw, &record), vec![0, 6, 10, 19]);
+    }
+
+    #[test]
+    fn good_rows() {
+        let text = "# a comment\nbench, const, type, pattern\n\n\
+                    math, 0, linear, (+ ?a ?b)\n\
+                    math, 1, ground, (+ ?a 1)\n\
+                    lambda, 0, linear, (app ?a ?b)\n\
+                    math, 0, linear, (+  ?a ?b)\n";
+        let pats = read_patterns::<Math>("test.csv", text, "math").unwrap();
+        let types: Vec<_> = pats.iter().map(|p| (p.category, p.consts)).collect();
+        // the last row is the first one again
+        assert_eq!(
+            types,
+            vec![(PatternType::Linear, 0), (PatternType::Ground, 1)]
+        );
+    }
+
+    #[test]
+    fn bad_header() {
+        let text = "bench, type, pattern\nmath, linear, (+ ?a ?b)\n";
+        let errors = read_patterns::<Math>("test.csv", text, "math").unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["test.csv:1:1: expected the header bench, const, type, pattern"]
+        );
+    }
+
+    #[test]
+    fn bad_fields() {
+        let errors = errors(&[
+            "math, 0, lineer, (+ ?a ?b)",
+            "math, x, linear, (+ ?a ?b)",
+            "math, 0, linear",
+        ]);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(
+            errors[0],
+            "test.csv:2:10: pattern type should be one of linear, nonlinear, or ground, got lineer"
+        );
+        assert!(errors[1].starts_with("test.csv:3:7: "), "{}", errors[1]);
+        assert_eq!(errors[2], "test.csv:4:16: expected 4 fields, found 3");
+    }
+
+    #[test]
+    fn wrong_const_count() {
+        assert_eq!(
+            errors(&["math, 1, linear, (+ ?a ?b)"]),
+            vec![
+                "test.csv:2:18: pattern has 0 constants and type linear, \
+                 but the row says 1 and linear"
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_operators() {
+        assert_eq!(
+            errors(&[
+                "math, 0, linear, (+ ?a (frob ?b))",
+                "math, 1, ground, (+ ?a (d ?b))",
+                "math, 1, ground, (+ ?a frob)",
+            ]),
+            vec![
+                "test.csv:2:25: no operator frob with 1 children in this language",
+                "test.csv:3:25: no operator d with 1 children in this language",
+                "test.csv:4:24: no constant frob in this language",
+            ]
+        );
+    }
+
+    #[test]
+    fn unbalanced_parentheses() {
+        assert_eq!(
+            errors(&["math, 0, linear, (+ ?a ?b", "math, 0, linear, (+ ?a ?b))"]),
+            vec![
+                "test.csv:2:19: unbalanced parentheses",
+                "test.csv:3:27: unbalanced parentheses",
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_types_of_old_results() {
+        let text = "bench, const, type, pattern\nmath, 0, nonlinear, (+  ?a ?a)\nmath, 0\n";
+        let types = declared_types(text);
+        assert_eq!(types.len(), 1);
+        assert_eq!(types["(+ ?a ?a)"], "nonlinear");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::*;
+use egg::{define_language, rewrite as rw, Id, Symbol};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+pub fn relational_bench() -> Result<Bench<Rel, Scope>, Vec<String>> {
+    Ok(Bench {
+        name: "relational".into(),
+        start_exprs: [
+            // TPC-H Q3: shipping priority
+            "(agg (list l_orderkey (list o_orderdate o_shippriority))
+                  (sum (* l_extendedprice (- 1 l_discount)))
+                  (select (and (= c_mktsegment building)
+                          (and (= c_custkey o_custkey)
+                          (and (= l_orderkey o_orderkey)
+                          (and (< o_orderdate 19950315)
+                               (> l_shipdate 19950315)))))
+                      (join true (join true customer orders) lineitem)))",
+            // TPC-H Q5: local supplier volume
+            "(agg n_name
+                  (sum (* l_extendedprice (- 1 l_discount)))
+                  (select (and (= c_custkey o_custkey)
+                          (and (= l_orderkey o_orderkey)
+                          (and (= l_suppkey s_suppkey)
+                          (and (= c_nationkey s_nationkey)
+                          (and (= s_nationkey n_nationkey)
+                          (and (= n_regionkey r_regionkey)
+                          (and (= r_name asia)
+                          (and (> o_orderdate 19940101)
+                               (< o_orderdate 19950101)))))))))
+                      (join true
+                          (join true
+                              (join true (join true customer orders) lineitem)
+                              supplier)
+                          (join true nation region))))",
+            // TPC-H Q10: returned item reporting
+            "(project (list c_custkey (list c_name n_name))
+                (agg (list c_custkey (list c_name n_name))
+                     (sum (* l_extendedprice (- 1 l_discount)))
+                     (select (and (= l_returnflag r)
+                             (and (> o_orderdate 19931001)
+                                  (< o_orderdate 19940101)))
+                         (join (= c_nationkey n_nationkey)
+                             (join (= l_orderkey o_orderkey)
+                                 (join (= c_custkey o_custkey) customer orders)
+                                 lineitem)
+                             nation))))",
+            // TPC-H Q16-style: parts from two supplier sets
+            "(project (list p_brand (list p_type p_size))
+                (union
+                    (select (= s_comment complaints)
+                        (join (= ps_suppkey s_suppkey)
+                            (join (= p_partkey ps_partkey) part partsupp)
+                            supplier))
+                    (select (< p_size 10)
+                        (join (= p_partkey ps_partkey) part partsupp))))",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rules: rules(),
+        bench_pats: parse_patterns("relational")?,
+        conditionals: vec![],
+        has_data: Some(|tables| !tables.is_empty()),
+    })
+}
+
+type EGraph = egg::EGraph<Rel, Scope>;
+
+define_language! {
+    pub enum Rel {
+        // predicate, input
+        "select" = Select([Id; 2]),
+        // columns, input
+        "project" = Project([Id; 2]),
+        // predicate, left, right
+        "join" = Join([Id; 3]),
+        "union" = Union([Id; 2]),
+        // group-by columns, aggregate, input
+        "agg" = Agg([Id; 3]),
+        "list" = List([Id; 2]),
+
+        "sum" = Sum(Id),
+        "count" = Count(Id),
+        "and" = And([Id; 2]),
+        "=" = Eq([Id; 2]),
+        "<" = Lt([Id; 2]),
+        ">" = Gt([Id; 2]),
+        "+" = Add([Id; 2]),
+        "-" = Sub([Id; 2]),
+        "*" = Mul([Id; 2]),
+
+        Bool(bool),
+        Num(i32),
+        Symbol(Symbol),
+    }
+}
+
+/// TPC-H tables and the prefix their columns are named with.
+const CATALOG: &[(&str, &str)] = &[
+    ("customer", "c"),
+    ("orders", "o"),
+    ("lineitem", "l"),
+    ("supplier", "s"),
+    ("nation", "n"),
+    ("region", "r"),
+    ("part", "p"),
+    ("partsupp", "ps"),
+];
+
+/// Tracks the base tables an e-class touches. For a relation that is the
+/// tables it reads from, for a scalar expression the tables of the columns
+/// it mentions.
+#[derive(Default, Clone)]
+pub struct Scope;
+impl Analysis<Rel> for Scope {
+    type Data = BTreeSet<&'static str>;
+
+    fn make(egraph: &EGraph, enode: &Rel) -> Self::Data {
+        let mut tables = BTreeSet::new();
+        match enode {
+            Rel::Symbol(s) => {
+                let s = s.to_string();
+                let column_prefix = s.split_once('_').map(|(prefix, _)| prefix);
+                for (table, prefix) in CATALOG {
+                    if s == *table || column_prefix == Some(*prefix) {
+                        tables.insert(*table);
+                    }
+                }
+            }
+            // only the input of these determines which tables are in scope
+            Rel::Project([_, r]) | Rel::Agg([_, _, r]) => tables.extend(&egraph[*r].data),
+            _ => enode.for_each(|c| tables.extend(&egraph[c].data)),
+        }
+        tables
+    }
+
+    fn merge(&self, to: &mut Self::Data, from: Self::Data) -> Option<Ordering> {
+        let before_len = to.len();
+        to.retain(|t| from.contains(t));
+        match (to.len() == before_len, to.len() == from.len()) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+}
+
+fn var(s: &str) -> Var {
+    s.parse().unwrap()
+}
+
+/// The predicate `p` only mentions tables that the relations in `rels` read.
+fn refers_within(p: &str, rels: &[&str]) -> impl Fn(&mut EGraph, Id, &Subst) -> bool {
+    let p = var(p);
+    let rels: Vec<Var> = rels.iter().map(|r| var(r)).collect();
+    move |egraph, _, subst| {
+        egraph[subst[p]]
+            .data
+            .iter()
+            .all(|t| rels.iter().any(|r| egraph[subst[*r]].data.contains(t)))
+    }
+}
+
+#[rustfmt::skip]
+pub fn rules() -> Vec<Rewrite<Rel, Scope>> { vec![
+    rw!("and-comm";  "(and ?p ?q)"          => "(and ?q ?p)"),
+    rw!("and-assoc"; "(and ?p (and ?q ?r))" => "(and (and ?p ?q) ?r)"),
+    rw!("eq-comm";   "(= ?a ?b)"            => "(= ?b ?a)"),
+    rw!("and-true";  "(and ?p true)"        => "?p"),
+
+    rw!("select-true";  "(select true ?r)"           => "?r"),
+    rw!("select-split"; "(select (and ?p ?q) ?r)"    => "(select ?p (select ?q ?r))"),
+    rw!("select-merge"; "(select ?p (select ?q ?r))" => "(select (and ?p ?q) ?r)"),
+
+    rw!("select-push-left";
+        "(select ?p (join ?q ?a ?b))" => "(join ?q (select ?p ?a) ?b)"
+        if refers_within("?p", &["?a"])),
+    rw!("select-push-right";
+        "(select ?p (join ?q ?a ?b))" => "(join ?q ?a (select ?p ?b))"
+        if refers_within("?p", &["?b"])),
+    rw!("select-into-join"; "(select ?p (join ?q ?a ?b))" => "(join (and ?p ?q) ?a ?b)"),
+    rw!("join-to-select";   "(join (and ?p ?q) ?a ?b)"    => "(select ?p (join ?q ?a ?b))"),
+
+    rw!("join-comm"; "(join ?p ?a ?b)" => "(join ?p ?b ?a)"),
+    rw!("join-assoc";
+        "(join ?p (join ?q ?a ?b) ?c)" => "(join ?q ?a (join ?p ?b ?c))"
+        if refers_within("?p", &["?b", "?c"])),
+    rw!("join-assoc-rev";
+        "(join ?p ?a (join ?q ?b ?c))" => "(join ?q (join ?p ?a ?b) ?c)"
+        if refers_within("?p", &["?a", "?b"])),
+
+    rw!("union-comm";   "(union ?a ?b)"            => "(union ?b ?a)"),
+    rw!("union-assoc";  "(union ?a (union ?b ?c))" => "(union (union ?a ?b) ?c)"),
+    rw!("select-union"; "(select ?p (union ?a ?b))" => "(union (select ?p ?a) (select ?p ?b))"),
+    rw!("union-select"; "(union (select ?p ?a) (select ?p ?b))" => "(select ?p (union ?a ?b))"),
+
+    rw!("project-merge"; "(project ?c (project ?d ?r))" => "(project ?c ?r)"),
+    rw!("project-union"; "(project ?c (union ?a ?b))"   => "(union (project ?c ?a) (project ?c ?b))"),
+]}
@@ -0,0 +1,590 @@
+//! The `report` subcommand: the table of `compare.py`, computed from result
+//! files without Python.
+//!
+//! For each bench and e-graph size there are two rows. Row 0 compares EM
+//! against the first GJ run and row 1 against the second, which reuses the
+//! indices GJ built in the first. The ratios are EM time over GJ time, so
+//! above 1 means GJ was faster.
+
+use crate::environment;
+use crate::html;
+use crate::patterns::{declared_types, PATTERNS_FILE};
+use crate::status::Status;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ReportOpt {
+    /// Result files written by the benchmark, read as one run
+    #[structopt(required = true)]
+    files: Vec<PathBuf>,
+    /// How long to assume timeouts took in seconds (default is the reported time)
+    #[structopt(long)]
+    timeout: Option<f64>,
+    /// Show all e-graphs, not only the biggest of each bench
+    #[structopt(long)]
+    all_egraphs: bool,
+    /// Split each row by pattern type (linear, nonlinear, ground)
+    #[structopt(long)]
+    by_category: bool,
+    /// text, markdown, csv, or html for a page with charts of every pattern
+    #[structopt(long, default_value = "text")]
+    format: Format,
+    /// Report files together even if they were run in different environments
+    #[structopt(long)]
+    force: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Markdown,
+    Csv,
+    Html,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "csv" => Ok(Format::Csv),
+            "html" => Ok(Format::Html),
+            _ => Err(format!(
+                "unknown format {}, expected text, markdown, csv or html",
+                s
+            )),
+        }
+    }
+}
+
+/// The columns of a `BenchRecord` that the report needs. Columns added after
+/// the first results were written are optional.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResultRow {
+    pub benchmark: String,
+    pub node_size: usize,
+    #[serde(default)]
+    pub fingerprint: String,
+    pub algo: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub mode: String,
+    /// Status of the search, missing in results that only have `time`.
+    #[serde(default)]
+    pub status: Option<Status>,
+    #[serde(default)]
+    pub time_ns: Option<f64>,
+    /// Microseconds, negative for timeouts, in results written before
+    /// `status` and `time_ns`.
+    #[serde(default)]
+    pub time: Option<f64>,
+    pub result_size: String,
+    pub repeat_time: usize,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl ResultRow {
+    pub fn status(&self) -> Status {
+        match (self.status, self.time) {
+            (Some(status), _) => status,
+            (None, Some(t)) if t < 0.0 => Status::Timeout,
+            (None, _) => Status::Ok,
+        }
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.status() == Status::Timeout
+    }
+
+    /// The time in microseconds, with timeouts counted as `timeout` seconds
+    /// if given and 0 as 1 so ratios stay finite. Only meaningful for
+    /// measured rows, see `Status::is_measured`.
+    pub fn micros(&self, timeout: Option<f64>) -> f64 {
+        let micros = match (self.time_ns, self.time) {
+            (Some(ns), _) => ns / 1e3,
+            (None, t) => t.unwrap_or(0.0).abs(),
+        };
+        match timeout {
+            Some(t) if self.timed_out() => t * 1e6,
+            _ => micros.max(1.0),
+        }
+    }
+
+    /// Rule searchers from `--conditional` runs are summarized as their own
+    /// bench, like `math/post`.
+    pub fn bench(&self) -> String {
+        match self.mode.as_str() {
+            "" | "pattern" => self.benchmark.clone(),
+            mode => format!("{}/{}", self.benchmark, mode),
+        }
+    }
+}
+
+/// Reads the rows of every file, as JSON lines if its extension is `jsonl`
+/// and as CSV otherwise, reporting the file and position of any row that
+/// doesn't parse.
+pub fn read_results(files: &[PathBuf]) -> Result<Vec<ResultRow>, String> {
+    let mut rows = vec![];
+    for file in files {
+        if file.extension() == Some("jsonl".as_ref()) {
+            let text = fs::read_to_string(file)
+                .map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+            rows.extend(parse_jsonl(&text).map_err(|e| format!("{}: {}", file.display(), e))?);
+            continue;
+        }
+        let mut rdr = csv::Reader::from_path(file)
+            .map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+        for row in rdr.deserialize() {
+            rows.push(row.map_err(|e| format!("{}: {}", file.display(), e))?);
+        }
+    }
+    Ok(rows)
+}
+
+/// The result records of a `--format jsonl` file, skipping its header.
+fn parse_jsonl(text: &str) -> Result<Vec<ResultRow>, String> {
+    let mut rows = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |e: serde_json::Error| format!("line {}: {}", i + 1, e);
+        let mut value: serde_json::Value = serde_json::from_str(line).map_err(error)?;
+        if value["type"] != "result" {
+            continue;
+        }
+        // a number in JSON, kept as the text of the CSV column
+        if value["result_size"].is_number() {
+            value["result_size"] = value["result_size"].to_string().into();
+        }
+        rows.push(serde_json::from_value(value).map_err(error)?);
+    }
+    Ok(rows)
+}
+
+/// The rows of one pattern on one e-graph, by algorithm and repeat.
+pub type Runs<'a> = HashMap<(&'a str, usize), Vec<&'a ResultRow>>;
+
+/// One line of the table.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub index: usize,
+    pub bench: String,
+    pub size: usize,
+    pub category: Option<String>,
+    /// Patterns where GJ was faster.
+    pub gj: usize,
+    /// Patterns where EM was at least as fast.
+    pub em: usize,
+    /// Patterns where EM timed out.
+    pub timeouts: usize,
+    /// Total EM time over total GJ time.
+    pub total: f64,
+    pub hmean: f64,
+    pub gmean: f64,
+    pub best: f64,
+    pub median: f64,
+    pub worst: f64,
+}
+
+/// Warns on stderr about the searches that panicked, ran out of memory or
+/// were skipped, which the report leaves out rather than count as slow.
+pub fn warn_failures(rows: &[ResultRow]) {
+    let failed: Vec<&ResultRow> = rows.iter().filter(|r| !r.status().is_measured()).collect();
+    if failed.is_empty() {
+        return;
+    }
+    eprintln!(
+        "warning: {} searches failed and are left out of the report:",
+        failed.len()
+    );
+    for row in failed {
+        eprintln!(
+            "  {} {} {} {}: {} {}",
+            row.bench(),
+            row.node_size,
+            row.algo,
+            row.repeat_time,
+            row.status(),
+            row.pattern
+        );
+        if !row.message.is_empty() {
+            eprintln!("    {}", row.message);
+        }
+    }
+}
+
+/// Summarizes the rows as `compare.py` does, printing warnings for results
+/// that differ between the strategies and for GJ timeouts to stderr. Failed
+/// searches are left out, so a pattern only counts if both strategies have
+/// a measured time.
+pub fn summarize(
+    rows: &[ResultRow],
+    timeout: Option<f64>,
+    all_egraphs: bool,
+    by_category: bool,
+) -> Vec<Summary> {
+    let mut benches: BTreeMap<String, BTreeMap<usize, BTreeMap<&str, Runs>>> = BTreeMap::new();
+    for row in rows.iter().filter(|r| r.status().is_measured()) {
+        benches
+            .entry(row.bench())
+            .or_default()
+            .entry(row.node_size)
+            .or_default()
+            .entry(&row.pattern)
+            .or_default()
+            .entry((&row.algo, row.repeat_time))
+            .or_default()
+            .push(row);
+    }
+
+    // older results don't have the column, so look it up by pattern
+    let mut declared = None;
+    let mut category_of = |row: &ResultRow| {
+        if !row.category.is_empty() {
+            return row.category.clone();
+        }
+        let types = declared.get_or_insert_with(|| {
+            let text = std::fs::read_to_string(PATTERNS_FILE).unwrap_or_default();
+            declared_types(&text)
+        });
+        let pattern = row.pattern.split_whitespace().collect::<Vec<_>>().join(" ");
+        types.get(&pattern).cloned().unwrap_or_default()
+    };
+
+    let categories: Vec<Option<&str>> = if by_category {
+        vec![Some("linear"), Some("nonlinear"), Some("ground")]
+    } else {
+        vec![None]
+    };
+
+    let mut summaries = vec![];
+    for (bench, sizes) in &benches {
+        let biggest = *sizes.keys().max().unwrap();
+        for (size, pats) in sizes {
+            if !(all_egraphs || *size == biggest) {
+                continue;
+            }
+            for category in &categories {
+                for index in 0..2 {
+                    let mut em_faster = 0;
+                    let mut gj_faster = 0;
+                    let mut em_timeouts = 0;
+                    let mut em_times = vec![];
+                    let mut gj_times = vec![];
+                    for runs in pats.values() {
+                        let (em, gj) =
+                            match (runs.get(&("EMatch", 0)), runs.get(&("GenericJoin", index))) {
+                                (Some(em), Some(gj)) => (em, gj),
+                                _ => continue,
+                            };
+                        if let Some(category) = category {
+                            if category_of(em[0]) != *category {
+                                continue;
+                            }
+                        }
+                        let em_row = fastest(em, timeout);
+                        let gj_row = fastest(gj, timeout);
+                        if em_row.result_size != gj_row.result_size && !em_row.timed_out() {
+                            eprintln!("MISMATCH!\n{:?}\n{:?}", em_row, gj_row);
+                        }
+                        if gj_row.timed_out() {
+                            eprintln!(
+                                "WARNING: GJ TIMED OUT, THE REPORT MIGHT BE A LITTLE OFF\n{:?}\n{:?}",
+                                gj_row, em_row
+                            );
+                        }
+
+                        let em = em_row.micros(timeout);
+                        let gj = gj_row.micros(timeout);
+                        if gj < em {
+                            gj_faster += 1;
+                        } else {
+                            em_faster += 1;
+                        }
+                        if em_row.timed_out() {
+                            em_timeouts += 1;
+                        }
+                        em_times.push(em);
+                        gj_times.push(gj);
+                    }
+                    if em_times.is_empty() {
+                        continue;
+                    }
+
+                    let mut ratios: Vec<f64> = em_times
+                        .iter()
+                        .zip(&gj_times)
+                        .map(|(em, gj)| em / gj)
+                        .collect();
+                    ratios.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    let n = ratios.len() as f64;
+                    summaries.push(Summary {
+                        index,
+                        bench: bench.clone(),
+                        size: *size,
+                        category: category.map(String::from),
+                        gj: gj_faster,
+                        em: em_faster,
+                        timeouts: em_timeouts,
+                        total: em_times.iter().sum::<f64>() / gj_times.iter().sum::<f64>(),
+                        hmean: n / ratios.iter().map(|r| 1.0 / r).sum::<f64>(),
+                        gmean: (ratios.iter().map(|r| r.ln()).sum::<f64>() / n).exp(),
+                        best: ratios[ratios.len() - 1],
+                        median: median(&ratios),
+                        worst: ratios[0],
+                    });
+                }
+            }
+        }
+    }
+    summaries
+}
+
+pub fn fastest<'a>(rows: &[&'a ResultRow], timeout: Option<f64>) -> &'a ResultRow {
+    let key = |r: &&ResultRow| r.micros(timeout);
+    rows.iter()
+        .min_by(|a, b| key(a).partial_cmp(&key(b)).unwrap())
+        .unwrap()
+}
+
+/// The median of sorted, non-empty values.
+pub fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[mid]
+    } else {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    }
+}
+
+/// Formats a ratio in 7 columns like `compare.py`: `    .84`, `  13.62`
+/// and `  1.2e5`.
+pub fn fmt_x(ratio: f64) -> String {
+    if ratio > 1e4 {
+        let s = format!("{:.1e}", ratio);
+        let (m, e) = s.split_at(s.find('e').unwrap());
+        format!("{:>5}{}", m, e)
+    } else if ratio < 1.0 {
+        format!("{:>7.2}", ratio).replacen("0.", " .", 1)
+    } else {
+        format!("{:>7.2}", ratio)
+    }
+}
+
+fn print_text(summaries: &[Summary], by_category: bool) {
+    let type_header = if by_category { "      type," } else { "" };
+    println!(
+        "index,  bench,       size,{}  gj,  em, TO,   total,    hmean,    gmean,     best,     medn,    worst",
+        type_header
+    );
+    for s in summaries {
+        let type_column = match &s.category {
+            Some(c) => format!("{:>9}, ", c),
+            None => String::new(),
+        };
+        println!(
+            "{}, {:>10}, {:>10}, {}{:>3}, {:>3},  {}, {},  {},  {},  {},  {},  {}",
+            s.index,
+            s.bench,
+            s.size,
+            type_column,
+            s.gj,
+            s.em,
+            s.timeouts,
+            fmt_x(s.total),
+            fmt_x(s.hmean),
+            fmt_x(s.gmean),
+            fmt_x(s.best),
+            fmt_x(s.median),
+            fmt_x(s.worst)
+        );
+    }
+}
+
+/// The header and fields of each summary, for markdown and csv.
+fn columns(summaries: &[Summary], by_category: bool, fmt: fn(f64) -> String) -> Vec<Vec<String>> {
+    let mut header = vec!["index", "bench", "size"];
+    if by_category {
+        header.push("type");
+    }
+    header.extend(&[
+        "gj", "em", "timeouts", "total", "hmean", "gmean", "best", "median", "worst",
+    ]);
+    let mut table = vec![header.into_iter().map(String::from).collect()];
+    for s in summaries {
+        let mut row = vec![s.index.to_string(), s.bench.clone(), s.size.to_string()];
+        row.extend(s.category.clone());
+        row.extend(vec![
+            s.gj.to_string(),
+            s.em.to_string(),
+            s.timeouts.to_string(),
+        ]);
+        row.extend(
+            [s.total, s.hmean, s.gmean, s.best, s.median, s.worst]
+                .iter()
+                .map(|x| fmt(*x)),
+        );
+        table.push(row);
+    }
+    table
+}
+
+pub fn report(opt: &ReportOpt) {
+    if let Err(problems) = environment::check_compatible(&opt.files) {
+        let level = if opt.force { "warning" } else { "error" };
+        for p in problems {
+            eprintln!("{}: {}", level, p);
+        }
+        if !opt.force {
+            eprintln!("error: pass --force to report these files together anyway");
+            std::process::exit(1)
+        }
+    }
+    let rows = read_results(&opt.files).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1)
+    });
+    warn_failures(&rows);
+    let summaries = summarize(&rows, opt.timeout, opt.all_egraphs, opt.by_category);
+    match opt.format {
+        Format::Text => print_text(&summaries, opt.by_category),
+        Format::Markdown => {
+            let table = columns(&summaries, opt.by_category, |x| fmt_x(x).trim().to_string());
+            for (i, row) in table.iter().enumerate() {
+                println!("| {} |", row.join(" | "));
+                if i == 0 {
+                    println!("|{}", "---|".repeat(row.len()));
+                }
+            }
+        }
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(std::io::stdout());
+            for row in columns(&summaries, opt.by_category, |x| x.to_string()) {
+                wtr.write_record(&row).unwrap();
+            }
+            wtr.flush().unwrap();
+        }
+        Format::Html => print!("{}", html::render(&rows, &summaries, opt.timeout)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(csv: &str) -> Vec<ResultRow> {
+        csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn legacy_and_new_rows_agree() {
+        let legacy = parse(
+            "benchmark,node_size,algo,pattern,time,result_size,repeat_time\n\
+             math,1000,EMatch,(+ ?a ?b),1234.5,10,0\n\
+             math,1000,EMatch,(* ?a ?b),-60000000,0,0\n",
+        );
+        let new = parse(
+            "benchmark,node_size,fingerprint,algo,pattern,category,mode,status,time_ns,\
+             result_size,repeat_time,message\n\
+             math,1000,f,EMatch,(+ ?a ?b),linear,pattern,ok,1234500,10,0,\n\
+             math,1000,f,EMatch,(* ?a ?b),linear,pattern,timeout,60000000000,0,0,\n",
+        );
+        for (legacy, new) in legacy.iter().zip(&new) {
+            assert_eq!(legacy.status(), new.status());
+            assert_eq!(legacy.micros(None), new.micros(None));
+            assert_eq!(legacy.micros(Some(30.0)), new.micros(Some(30.0)));
+            assert_eq!(legacy.bench(), new.bench());
+        }
+        assert_eq!(new[0].status(), Status::Ok);
+        assert_eq!(new[0].micros(None), 1234.5);
+        assert_eq!(new[1].status(), Status::Timeout);
+        assert_eq!(new[1].micros(None), 60e6);
+        assert_eq!(new[1].micros(Some(30.0)), 30e6);
+    }
+
+    #[test]
+    fn csv_and_jsonl_read_the_same() {
+        let record = crate::BenchRecord {
+            benchmark: "math".into(),
+            node_limit: 1000,
+            node_size: 1234,
+            class_size: 567,
+            fingerprint: "f".into(),
+            config_hash: "c".into(),
+            shard: Default::default(),
+            algo: "GenericJoin".into(),
+            pattern_id: "math-1".into(),
+            pattern: "(+ ?a ?b)".into(),
+            category: crate::PatternType::Linear,
+            consts: 0,
+            mode: "post",
+            status: Status::Oom,
+            time_ns: 1500,
+            result_size: 42,
+            agm_bound: 100.0,
+            survivors: 7,
+            repeat_time: 1,
+            atoms: 1,
+            query_vars: 3,
+            hypergraph_size: 3,
+            distinct_vars: 2,
+            repeated_vars: 0,
+            acyclic: true,
+            depth: 1,
+            edge_cover: 1.0,
+            message: "grew, by a lot".into(),
+        };
+        let opt = crate::Opt::from_iter(&["egg-bench"]);
+        let json = crate::JsonRecord {
+            kind: "result",
+            record: &record,
+            sample: 0,
+            stop_reason: "NodeLimit(1000)",
+            opt: &opt,
+        };
+        let jsonl = format!(
+            "{{\"type\":\"header\"}}\n{}\n",
+            serde_json::to_string(&json).unwrap()
+        );
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(&record).unwrap();
+        let csv = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+
+        let from_jsonl = parse_jsonl(&jsonl).unwrap();
+        let from_csv = parse(&csv);
+        assert_eq!(format!("{:?}", from_jsonl), format!("{:?}", from_csv));
+        assert_eq!(from_jsonl[0].result_size, "42");
+        assert_eq!(from_jsonl[0].status(), Status::Oom);
+        assert_eq!(from_jsonl[0].bench(), "math/post");
+        assert!(parse_jsonl("{\"type\":\"result\"}\n")
+            .unwrap_err()
+            .starts_with("line 1: "));
+    }
+
+    #[test]
+    fn failed_rows() {
+        let rows = parse(
+            "benchmark,node_size,algo,pattern,mode,status,time_ns,result_size,repeat_time,message\n\
+             math,1000,GenericJoin,(+ ?a ?b),post,panic,5000,0,0,oops\n\
+             math,1000,GenericJoin,(+ ?a ?b),post,skipped,0,0,1,\n",
+        );
+        assert_eq!(rows[0].status(), Status::Panic);
+        assert_eq!(rows[0].message, "oops");
+        assert_eq!(rows[1].status(), Status::Skipped);
+        assert!(rows.iter().all(|r| !r.status().is_measured()));
+        assert_eq!(rows[0].bench(), "math/post");
+    }
+}
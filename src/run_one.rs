@@ -0,0 +1,245 @@
+//! The `run-one` subcommand: searches a single pattern of a bench in a loop,
+//! to profile just that case with an external profiler like `perf`.
+//!
+//! The e-graph is built once like in a run. Then `run-one: start` is printed,
+//! the search is repeated `--count` times or for `--duration` seconds on the
+//! same e-graph, and `run-one: end` is printed, so everything between the two
+//! markers is the search. With `--pushdown`, the filtered copy of the e-graph
+//! is built once, before the start marker.
+//!
+//! The e-graph is only the one of a run if `--sizes` and `--fpcore` are the
+//! same, so the node limit it was grown to and its fingerprint are printed,
+//! to check them against the `node_limit` and `fingerprint` of the results.
+
+use crate::conditional::ConditionalSearcher;
+use crate::metrics::RelationSizes;
+use crate::{saturate, with_bench, Bench, BenchVisitor, Opt};
+use egg::*;
+use std::fmt::Display;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct RunOneOpt {
+    /// The bench, like math or lambda
+    bench: String,
+    /// The e-graph: its node limit, or n for generated suites like adversarial
+    size: usize,
+    /// The pattern's id or its text, as in the results
+    pattern: String,
+    /// gj or em (GenericJoin and EMatch work too)
+    strategy: String,
+    /// The node limits the e-graph is grown through before `size`; by default
+    /// those of a run with the default --sizes
+    #[structopt(long, value_delimiter = ",")]
+    sizes: Option<Vec<usize>>,
+    /// Search this many times
+    #[structopt(long)]
+    count: Option<usize>,
+    /// Without --count, search for this many seconds
+    #[structopt(long, default_value = "10")]
+    duration: f64,
+    /// FPCore files or directories whose cores are added to the math start
+    /// expressions, as with the --fpcore of the run
+    #[structopt(long, value_delimiter = ",")]
+    fpcore: Vec<String>,
+    /// Search the left-hand side of a conditional rule, given by its id or
+    /// its text, and check its conditions, as with --conditional
+    #[structopt(long)]
+    conditional: bool,
+    /// With --conditional, search a copy of the e-graph that single-variable
+    /// guards were applied to, as with the --pushdown of the run
+    #[structopt(long, requires = "conditional")]
+    pushdown: bool,
+}
+
+const DEFAULT_SIZES: &[usize] = &[10000, 100000, 200000, 300000];
+
+pub fn run_one(opt: &RunOneOpt) {
+    let mut run_opt = Opt::from_iter(&["egg-bench"]);
+    run_opt.fpcore = opt.fpcore.clone();
+    run_opt.import_fpcore().unwrap_or_else(|e| fail(e));
+    run_opt.conditional = opt.conditional;
+    if let Err(problems) = with_bench(&run_opt, &opt.bench, RunOne(opt)) {
+        for p in problems {
+            eprintln!("error: {}", p);
+        }
+        std::process::exit(1)
+    }
+}
+
+struct RunOne<'a>(&'a RunOneOpt);
+
+impl BenchVisitor for RunOne<'_> {
+    fn visit<L, A>(self, bench: Bench<L, A>, generate: Option<fn(usize) -> EGraph<L, A>>)
+    where
+        A: Analysis<L> + Default + Clone + Send + Sync,
+        L: Language + FromOp + Sync + Send + Display,
+        <A as egg::Analysis<L>>::Data: Send + Clone,
+        <L as egg::Language>::Operator: Send + Sync,
+    {
+        let opt = self.0;
+        match generate {
+            Some(generate) => {
+                let (target, strategy) = find(opt, &bench);
+                search_loop(opt, &target, strategy, generate(opt.size), opt.size)
+            }
+            None => grown(opt, bench),
+        }
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1)
+}
+
+/// Grows the e-graph through the sizes like `run_bench` does. If it
+/// saturates before `size`, a run has no results for `size`, so the
+/// saturated e-graph is searched with a warning.
+fn grown<L, A>(opt: &RunOneOpt, bench: Bench<L, A>)
+where
+    A: Analysis<L> + Default + Clone,
+    L: Language + FromOp + Display,
+    <A as egg::Analysis<L>>::Data: Clone,
+{
+    let (target, strategy) = find(opt, &bench);
+    let sizes = opt.sizes.as_deref().unwrap_or(DEFAULT_SIZES);
+    let mut egraph = EGraph::default();
+    let mut reached = 0;
+    for node_limit in sizes.iter().filter(|s| **s < opt.size).chain(&[opt.size]) {
+        let runner = saturate(&bench, egraph, *node_limit);
+        egraph = runner.egraph;
+        reached = *node_limit;
+        if let Some(StopReason::Saturated) = runner.stop_reason {
+            break;
+        }
+    }
+    if reached < opt.size {
+        eprintln!(
+            "warning: the e-graph saturated at node limit {}, so a run has no results for {}",
+            reached, opt.size
+        );
+    }
+    search_loop(opt, &target, strategy, egraph, reached)
+}
+
+/// What to search: a pattern, or the left-hand side of a conditional rule
+/// whose matches are then checked against its conditions.
+enum Target<'a, L: Language, A: Analysis<L>> {
+    Pattern(&'a Pattern<L>),
+    Conditional(&'a ConditionalSearcher<L, A>),
+}
+
+impl<L: Language + FromOp, A: Analysis<L>> Target<'_, L, A> {
+    /// The number of matches, as in the `result_size` column.
+    fn search(&self, egraph: &mut EGraph<L, A>) -> usize {
+        match self {
+            Target::Pattern(p) => p.search(egraph).iter().map(|m| m.substs.len()).sum(),
+            Target::Conditional(c) => c.search(egraph).0,
+        }
+    }
+}
+
+/// The pattern and strategy to search, checked before the e-graph is built.
+fn find<'a, L, A>(opt: &RunOneOpt, bench: &'a Bench<L, A>) -> (Target<'a, L, A>, Strategy)
+where
+    A: Analysis<L>,
+    L: Language + FromOp + Display,
+{
+    let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+    let wanted = normalize(&opt.pattern);
+    let is_wanted =
+        |id: &str, p: &Pattern<L>| id == wanted || normalize(&p.pretty(usize::MAX)) == wanted;
+    let target = if opt.conditional {
+        match bench
+            .conditionals
+            .iter()
+            .find(|c| is_wanted(&format!("{}-{}", bench.name, c.name), &c.pattern))
+        {
+            Some(c) => Target::Conditional(c),
+            None => fail(format!(
+                "{} has no conditional rule {}",
+                bench.name, opt.pattern
+            )),
+        }
+    } else {
+        match bench
+            .bench_pats
+            .iter()
+            .find(|p| is_wanted(&p.id, &p.pattern))
+        {
+            Some(p) => Target::Pattern(&p.pattern),
+            None => fail(format!("{} has no pattern {}", bench.name, opt.pattern)),
+        }
+    };
+    let strategy = match opt.strategy.as_str() {
+        "gj" | "GenericJoin" => Strategy::GenericJoin,
+        "em" | "EMatch" => Strategy::EMatch,
+        s => fail(format!("strategy should be gj or em, got {}", s)),
+    };
+    (target, strategy)
+}
+
+fn search_loop<L, A>(
+    opt: &RunOneOpt,
+    target: &Target<L, A>,
+    strategy: Strategy,
+    mut egraph: EGraph<L, A>,
+    node_limit: usize,
+) where
+    A: Analysis<L> + Clone,
+    L: Language + FromOp + Display,
+    <A as egg::Analysis<L>>::Data: Clone,
+{
+    egraph.strategy = strategy;
+    println!(
+        "e-graph: node limit {}, {} nodes, {} classes, fingerprint {}",
+        node_limit,
+        egraph.total_number_of_nodes(),
+        egraph.number_of_classes(),
+        RelationSizes::of(&egraph).fingerprint()
+    );
+    if let (true, Target::Conditional(c)) = (opt.pushdown, target) {
+        egraph = c.pushdown(&egraph);
+        egraph.strategy = strategy;
+        println!(
+            "pushdown: {} nodes, {} classes",
+            egraph.total_number_of_nodes(),
+            egraph.number_of_classes()
+        );
+    }
+
+    let duration = Duration::from_secs_f64(opt.duration);
+    let mut times = vec![];
+    let mut result_size = 0;
+    println!("run-one: start pid {}", std::process::id());
+    std::io::stdout().flush().unwrap();
+    let start = Instant::now();
+    while match opt.count {
+        Some(count) => times.len() < count,
+        None => start.elapsed() < duration,
+    } {
+        let time = Instant::now();
+        result_size = target.search(&mut egraph);
+        times.push(time.elapsed());
+    }
+    let elapsed = start.elapsed();
+    println!("run-one: end");
+    std::io::stdout().flush().unwrap();
+
+    times.sort();
+    if times.is_empty() {
+        return;
+    }
+    println!(
+        "{} searches in {:?}, {} results each: min {:?}, median {:?}, max {:?}",
+        times.len(),
+        elapsed,
+        result_size,
+        times[0],
+        times[times.len() / 2],
+        times[times.len() - 1]
+    );
+}
@@ -0,0 +1,673 @@
+//! Splitting a run across machines with `--shard i/n`, and the `merge`
+//! subcommand that puts the results of the shards back together.
+//!
+//! Whole e-graphs are given out by a hash of the bench and the e-graph size,
+//! so a search is in the same shard whatever else is run, and a shard only
+//! grows a bench's e-graph as far as it needs. With `--shard-by-pattern` the
+//! pattern id is hashed too, which splits the searches more evenly, but then
+//! every shard grows each bench's e-graph up to about the biggest size.
+//!
+//! Every result records its shard, as does the env file of a run, so that a
+//! shard that got no searches is known too. `merge` only merges the shards
+//! 0 to n-1 of one n, each exactly once, and their stats files with them.
+
+use crate::environment;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Default for Shard {
+    fn default() -> Self {
+        Shard { index: 0, count: 1 }
+    }
+}
+
+impl Shard {
+    /// Whether the work with this key is this shard's.
+    pub fn owns(&self, key: &str) -> bool {
+        crate::patterns::fnv1a(key.bytes()) as usize % self.count == self.index
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("shard should be i/n with 0 <= i < n, got {}", s);
+        let (index, count) = s.split_once('/').ok_or_else(error)?;
+        let shard = Shard {
+            index: index.parse().map_err(|_| error())?,
+            count: count.parse().map_err(|_| error())?,
+        };
+        if shard.index < shard.count {
+            Ok(shard)
+        } else {
+            Err(error())
+        }
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl Serialize for Shard {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MergeOpt {
+    /// Results files of the shards, all CSV or all JSON Lines
+    #[structopt(required = true)]
+    shards: Vec<PathBuf>,
+    /// Where to write the merged results
+    #[structopt(short, long)]
+    output: PathBuf,
+    /// Merge even if the shards were run in different environments
+    #[structopt(long)]
+    force: bool,
+}
+
+/// A results file: the header of a JSON Lines file or of a CSV file, and
+/// its results as JSON objects, to check and write back.
+struct ShardFile {
+    header: Header,
+    rows: Vec<serde_json::Map<String, serde_json::Value>>,
+    /// The shard and config hash from the env file, or the JSON header.
+    run: Option<environment::RunConfig>,
+}
+
+enum Header {
+    Csv(csv::StringRecord),
+    Jsonl(String),
+}
+
+fn read(path: &Path) -> Result<ShardFile, String> {
+    let error = |e: &dyn Display| format!("{}: {}", path.display(), e);
+    if path.extension() == Some("jsonl".as_ref()) {
+        let file = fs::File::open(path).map_err(|e| error(&e))?;
+        let mut lines = BufReader::new(file).lines();
+        let header = match lines.next() {
+            Some(line) => line.map_err(|e| error(&e))?,
+            None => return Err(error(&"empty file")),
+        };
+        let mut rows = vec![];
+        for line in lines {
+            let line = line.map_err(|e| error(&e))?;
+            match serde_json::from_str(&line).map_err(|e| error(&e))? {
+                serde_json::Value::Object(row) => rows.push(row),
+                _ => return Err(error(&"a line isn't a JSON object")),
+            }
+        }
+        let json: serde_json::Value = serde_json::from_str(&header).map_err(|e| error(&e))?;
+        let run = match (json["config_hash"].as_str(), json["opt"]["shard"].as_str()) {
+            (Some(config_hash), Some(shard)) => Some(environment::RunConfig {
+                config_hash: config_hash.into(),
+                shard: shard.into(),
+            }),
+            _ => None,
+        };
+        Ok(ShardFile {
+            header: Header::Jsonl(header),
+            rows,
+            run: environment::read(path)?.and_then(|env| env.run).or(run),
+        })
+    } else {
+        let mut rdr = csv::Reader::from_path(path).map_err(|e| error(&e))?;
+        let header = rdr.headers().map_err(|e| error(&e))?.clone();
+        let mut rows = vec![];
+        for record in rdr.records() {
+            let record = record.map_err(|e| error(&e))?;
+            let row = header
+                .iter()
+                .zip(record.iter())
+                .map(|(k, v)| (k.to_string(), v.into()))
+                .collect();
+            rows.push(row);
+        }
+        Ok(ShardFile {
+            header: Header::Csv(header),
+            rows,
+            run: environment::read(path)?.and_then(|env| env.run),
+        })
+    }
+}
+
+/// A column as a string, whether it came from CSV or JSON.
+fn field(row: &serde_json::Map<String, serde_json::Value>, name: &str) -> String {
+    match row.get(name) {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Checks that the shards belong together and writes their results to one
+/// file, exiting with the problems if they don't.
+pub fn merge(opt: &MergeOpt) {
+    if let Err(problems) = environment::check_compatible(&opt.shards) {
+        if opt.force {
+            for p in problems {
+                eprintln!("warning: {}", p);
+            }
+        } else {
+            eprintln!("error: pass --force to merge these shards anyway");
+            fail(problems);
+        }
+    }
+    let files: Vec<ShardFile> = opt
+        .shards
+        .iter()
+        .map(|path| read(path))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| fail(vec![e]));
+
+    let mut problems = check_shards(&opt.shards, &files);
+    problems.extend(check_results(&opt.shards, &files));
+    problems.extend(check_stats_headers(&opt.shards));
+    if !problems.is_empty() {
+        fail(problems);
+    }
+
+    let cannot_write = |path: &Path, e: &dyn Display| -> ! {
+        fail(vec![format!("cannot write {}: {}", path.display(), e)])
+    };
+    let (_, first) = reference(&opt.shards, &files);
+    write(&opt.output, first, &files).unwrap_or_else(|e| cannot_write(&opt.output, &e));
+    let stats = crate::stats::path_for(&opt.output);
+    merge_stats(&opt.shards, &stats).unwrap_or_else(|e| cannot_write(&stats, &e));
+    if let Ok(Some(mut env)) = environment::read(&opt.shards[0]) {
+        if let Some(run) = &mut env.run {
+            run.shard = Shard::default().to_string();
+        }
+        let path = environment::path_for(&opt.output);
+        environment::write(&opt.output, &env).unwrap_or_else(|e| cannot_write(&path, &e));
+    }
+    let rows: usize = files.iter().map(|f| f.rows.len()).sum();
+    eprintln!(
+        "Merged {} results of {} shards into {}",
+        rows,
+        files.len(),
+        opt.output.display()
+    );
+}
+
+fn fail(problems: Vec<String>) -> ! {
+    for p in problems {
+        eprintln!("error: {}", p);
+    }
+    std::process::exit(1)
+}
+
+/// Checks that each file is one shard of the same n, and that together they
+/// are all n of them.
+fn check_shards(paths: &[PathBuf], files: &[ShardFile]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut shards: BTreeMap<usize, &PathBuf> = BTreeMap::new();
+    let mut count = None;
+    for (path, file) in paths.iter().zip(files) {
+        let mut names: Vec<String> = file.run.iter().map(|r| r.shard.clone()).collect();
+        for row in &file.rows {
+            let name = field(row, "shard");
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let shard: Shard = match &names[..] {
+            [] => {
+                problems.push(format!(
+                    "{} has no results and no {}, so its shard is unknown",
+                    path.display(),
+                    environment::path_for(path).display()
+                ));
+                continue;
+            }
+            [name] if name.is_empty() => {
+                problems.push(format!("{} has no shard column", path.display()));
+                continue;
+            }
+            [name] => match name.parse() {
+                Ok(shard) => shard,
+                Err(e) => {
+                    problems.push(format!("{}: {}", path.display(), e));
+                    continue;
+                }
+            },
+            _ => {
+                problems.push(format!(
+                    "{} has results of shards {}",
+                    path.display(),
+                    names.join(", ")
+                ));
+                continue;
+            }
+        };
+        match count {
+            None => count = Some((shard.count, path)),
+            Some((n, first)) if n != shard.count => {
+                problems.push(format!(
+                    "{} is a shard of {} but {} is one of {}",
+                    first.display(),
+                    n,
+                    path.display(),
+                    shard.count
+                ));
+                continue;
+            }
+            Some(_) => (),
+        }
+        if let Some(other) = shards.insert(shard.index, path) {
+            problems.push(format!(
+                "{} and {} are both shard {}",
+                other.display(),
+                path.display(),
+                shard
+            ));
+        }
+    }
+    if let Some((n, _)) = count {
+        let missing: Vec<String> = (0..n)
+            .filter(|i| !shards.contains_key(i))
+            .map(|i| Shard { index: i, count: n }.to_string())
+            .collect();
+        if !missing.is_empty() {
+            problems.push(format!("missing shards: {}", missing.join(", ")));
+        }
+    }
+    problems
+}
+
+/// Checks that the results of the shards have the same format, columns and
+/// config hash, that they built the same e-graphs, and that no search was
+/// run by two of them.
+fn check_results(paths: &[PathBuf], files: &[ShardFile]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut hashes: BTreeMap<String, Vec<&PathBuf>> = BTreeMap::new();
+    // which shard searched each pattern on each e-graph, and the e-graphs
+    let mut owners: HashMap<(String, String, String), &PathBuf> = HashMap::new();
+    let mut egraphs: HashMap<(String, String), (String, &PathBuf)> = HashMap::new();
+    // the columns to compare with; an empty CSV file has none
+    let (first_path, first) = reference(paths, files);
+    for (path, file) in paths.iter().zip(files) {
+        match (&file.header, &first.header) {
+            (Header::Csv(h), Header::Csv(first)) if h != first && !file.rows.is_empty() => problems
+                .push(format!(
+                    "{} has different columns than {}",
+                    path.display(),
+                    first_path.display()
+                )),
+            (Header::Csv(_), Header::Jsonl(_)) | (Header::Jsonl(_), Header::Csv(_)) => problems
+                .push(format!(
+                    "{} and {} have different formats",
+                    path.display(),
+                    first_path.display()
+                )),
+            _ => (),
+        }
+        let mut file_hashes = vec![];
+        if let Some(run) = &file.run {
+            file_hashes.push(run.config_hash.clone());
+        }
+        for row in &file.rows {
+            let hash = field(row, "config_hash");
+            if !file_hashes.contains(&hash) {
+                file_hashes.push(hash);
+            }
+            let bench = field(row, "benchmark");
+            let limit = field(row, "node_limit");
+            let fingerprint = field(row, "fingerprint");
+            match egraphs.get(&(bench.clone(), limit.clone())) {
+                Some((other, other_path)) if *other != fingerprint => push_new(
+                    &mut problems,
+                    format!(
+                        "{} and {} built different e-graphs for {} at size {}: {} vs {}",
+                        other_path.display(),
+                        path.display(),
+                        bench,
+                        limit,
+                        other,
+                        fingerprint
+                    ),
+                ),
+                Some(_) => (),
+                None => {
+                    egraphs.insert((bench.clone(), limit.clone()), (fingerprint, path));
+                }
+            }
+            let key = (bench, limit, field(row, "pattern_id"));
+            let owner = owners.entry(key.clone()).or_insert(path);
+            if *owner != path {
+                push_new(
+                    &mut problems,
+                    format!(
+                        "{} and {} both searched {} on {} at size {}",
+                        owner.display(),
+                        path.display(),
+                        key.2,
+                        key.0,
+                        key.1
+                    ),
+                );
+            }
+        }
+        for hash in file_hashes {
+            hashes.entry(hash).or_default().push(path);
+        }
+    }
+    if hashes.keys().any(|h| h.is_empty()) {
+        problems.push("some results have no config_hash, so they weren't sharded".into());
+    }
+    if hashes.len() > 1 {
+        for (hash, paths) in &hashes {
+            let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+            problems.push(format!("config {} in {}", hash, paths.join(", ")));
+        }
+        problems.push("the shards were run with different configurations".into());
+    }
+    problems
+}
+
+/// Adds a problem unless it's known, as every row of an e-graph or a pattern
+/// has the same problems.
+fn push_new(problems: &mut Vec<String>, problem: String) {
+    if !problems.contains(&problem) {
+        problems.push(problem);
+    }
+}
+
+/// The first file with results, whose header the others are compared with
+/// and written under, or the first file if none has results.
+fn reference<'a>(paths: &'a [PathBuf], files: &'a [ShardFile]) -> (&'a PathBuf, &'a ShardFile) {
+    paths
+        .iter()
+        .zip(files)
+        .find(|(_, f)| !f.rows.is_empty())
+        .unwrap_or((&paths[0], &files[0]))
+}
+
+/// Checks that the stats files of the shards have the same columns, so their
+/// rows can be written under one header. Empty ones have none.
+fn check_stats_headers(shards: &[PathBuf]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut first: Option<(csv::StringRecord, PathBuf)> = None;
+    for shard in shards {
+        let path = crate::stats::path_for(shard);
+        if !path.exists() {
+            continue;
+        }
+        let header = match csv::Reader::from_path(&path).and_then(|mut r| r.headers().cloned()) {
+            Ok(header) => header,
+            Err(e) => {
+                problems.push(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
+        match &first {
+            _ if header.is_empty() => (),
+            None => first = Some((header, path)),
+            Some((first, first_path)) if *first != header => problems.push(format!(
+                "{} has different columns than {}",
+                path.display(),
+                first_path.display()
+            )),
+            Some(_) => (),
+        }
+    }
+    problems
+}
+
+/// Writes the stats files of the shards to one, under the header of the
+/// first, see `check_stats_headers`. Shards that grew the same e-graph have
+/// the same stats for it, so each e-graph's are only taken from the first
+/// shard that has them. Shards without a stats file are skipped.
+fn merge_stats(shards: &[PathBuf], output: &Path) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_path(output)?;
+    let mut header_written = false;
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+    for shard in shards {
+        let path = crate::stats::path_for(shard);
+        if !path.exists() {
+            eprintln!("warning: {} has no {}", shard.display(), path.display());
+            continue;
+        }
+        let mut rdr = csv::Reader::from_path(&path)?;
+        let header = rdr.headers()?.clone();
+        if header.is_empty() {
+            continue;
+        }
+        if !header_written {
+            wtr.write_record(&header)?;
+            header_written = true;
+        }
+        // an e-graph is identified by the columns before the stat
+        let egraph_columns = header.iter().position(|c| c == "stat").unwrap_or(0);
+        let mut egraphs = HashSet::new();
+        for record in rdr.records() {
+            let record = record?;
+            let egraph: Vec<String> = record
+                .iter()
+                .take(egraph_columns)
+                .map(String::from)
+                .collect();
+            if !seen.contains(&egraph) {
+                wtr.write_record(&record)?;
+                egraphs.insert(egraph);
+            }
+        }
+        seen.extend(egraphs);
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes the rows of all files under the header of `first`.
+fn write(path: &Path, first: &ShardFile, files: &[ShardFile]) -> std::io::Result<()> {
+    match &first.header {
+        Header::Csv(header) => {
+            let mut wtr = csv::Writer::from_path(path)?;
+            wtr.write_record(header)?;
+            for row in files.iter().flat_map(|f| &f.rows) {
+                wtr.write_record(header.iter().map(|name| field(row, name)))?;
+            }
+            wtr.flush()
+        }
+        Header::Jsonl(header) => {
+            let mut file = std::io::BufWriter::new(fs::File::create(path)?);
+            // the options of the first shard, except that it's all of them now
+            let mut header: serde_json::Value = serde_json::from_str(header)?;
+            if let Some(opt) = header.get_mut("opt").and_then(|o| o.as_object_mut()) {
+                opt.insert("shard".into(), Shard::default().to_string().into());
+            }
+            header["merged"] = files.len().into();
+            writeln!(file, "{}", header)?;
+            for row in files.iter().flat_map(|f| &f.rows) {
+                writeln!(file, "{}", serde_json::Value::Object(row.clone()))?;
+            }
+            file.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Row = serde_json::Map<String, serde_json::Value>;
+
+    const COLUMNS: &[&str] = &[
+        "benchmark",
+        "node_limit",
+        "fingerprint",
+        "config_hash",
+        "shard",
+        "pattern_id",
+    ];
+
+    /// A result of the search of `pattern_id` on the bench's e-graph of size 10.
+    fn row(shard: &str, fingerprint: &str, pattern_id: &str) -> Row {
+        let values = ["math", "10", fingerprint, "c0ffee", shard, pattern_id];
+        COLUMNS
+            .iter()
+            .zip(values.iter())
+            .map(|(k, v)| (k.to_string(), (*v).into()))
+            .collect()
+    }
+
+    fn file(rows: Vec<Row>) -> ShardFile {
+        ShardFile {
+            header: Header::Csv(csv::StringRecord::from(COLUMNS.to_vec())),
+            rows,
+            run: None,
+        }
+    }
+
+    /// A shard without results, whose shard is only known from its env file.
+    fn empty(shard: &str) -> ShardFile {
+        ShardFile {
+            run: Some(environment::RunConfig {
+                config_hash: "c0ffee".into(),
+                shard: shard.into(),
+            }),
+            ..file(vec![])
+        }
+    }
+
+    fn paths(n: usize) -> Vec<PathBuf> {
+        (0..n)
+            .map(|i| PathBuf::from(format!("shard{}.csv", i)))
+            .collect()
+    }
+
+    #[test]
+    fn parse_shard() {
+        assert_eq!("1/3".parse(), Ok(Shard { index: 1, count: 3 }));
+        assert_eq!(Shard::default().to_string().parse(), Ok(Shard::default()));
+        for bad in &["3/3", "a/2", "1", "1/0", "-1/2"] {
+            assert!(bad.parse::<Shard>().is_err(), "{} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn complete_shards() {
+        let files = vec![file(vec![row("0/2", "f", "m1")]), empty("1/2")];
+        assert_eq!(check_shards(&paths(2), &files), Vec::<String>::new());
+    }
+
+    #[test]
+    fn duplicate_and_missing_shards() {
+        let files = vec![file(vec![row("0/3", "f", "m1")]), empty("0/3")];
+        assert_eq!(
+            check_shards(&paths(2), &files),
+            vec![
+                "shard0.csv and shard1.csv are both shard 0/3",
+                "missing shards: 1/3, 2/3",
+            ]
+        );
+    }
+
+    #[test]
+    fn shards_of_different_counts() {
+        let files = vec![empty("0/2"), empty("1/3")];
+        let problems = check_shards(&paths(2), &files);
+        assert_eq!(
+            problems[0],
+            "shard0.csv is a shard of 2 but shard1.csv is one of 3"
+        );
+    }
+
+    #[test]
+    fn unknown_shards() {
+        let mixed = file(vec![row("0/2", "f", "m1"), row("1/2", "f", "m2")]);
+        let files = vec![mixed, file(vec![])];
+        assert_eq!(
+            check_shards(&paths(2), &files),
+            vec![
+                "shard0.csv has results of shards 0/2, 1/2",
+                "shard1.csv has no results and no shard1-env.json, so its shard is unknown",
+            ]
+        );
+    }
+
+    #[test]
+    fn results_that_belong_together() {
+        let files = vec![
+            file(vec![row("0/2", "f", "m1"), row("0/2", "f", "m1")]),
+            file(vec![row("1/2", "f", "m2")]),
+            empty("1/2"),
+        ];
+        assert_eq!(check_results(&paths(3), &files), Vec::<String>::new());
+    }
+
+    #[test]
+    fn different_egraphs() {
+        let files = vec![
+            file(vec![row("0/2", "f", "m1"), row("0/2", "f", "m3")]),
+            file(vec![row("1/2", "g", "m2"), row("1/2", "g", "m4")]),
+        ];
+        assert_eq!(
+            check_results(&paths(2), &files),
+            vec!["shard0.csv and shard1.csv built different e-graphs for math at size 10: f vs g"]
+        );
+    }
+
+    #[test]
+    fn searched_twice() {
+        let files = vec![
+            file(vec![row("0/2", "f", "m1")]),
+            file(vec![row("1/2", "f", "m1")]),
+        ];
+        assert_eq!(
+            check_results(&paths(2), &files),
+            vec!["shard0.csv and shard1.csv both searched m1 on math at size 10"]
+        );
+    }
+
+    #[test]
+    fn different_configurations() {
+        let mut other = row("1/2", "f", "m2");
+        other.insert("config_hash".into(), "decaf".into());
+        let files = vec![file(vec![row("0/2", "f", "m1")]), file(vec![other])];
+        let problems = check_results(&paths(2), &files);
+        assert_eq!(
+            problems.last().unwrap(),
+            "the shards were run with different configurations"
+        );
+    }
+
+    #[test]
+    fn stats_of_an_egraph_are_merged_once() {
+        let dir = std::env::temp_dir().join(format!("egg-bench-shard-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let header = "benchmark,node_size,class_size,stat,key,value\n";
+        let shards = vec![dir.join("a.csv"), dir.join("b.csv"), dir.join("c.csv")];
+        let a = "math,10,5,nodes,+,3\nmath,10,5,nodes,*,2\n";
+        let b = "math,10,5,nodes,+,3\nmath,10,5,nodes,*,2\nmath,20,8,nodes,+,6\n";
+        fs::write(crate::stats::path_for(&shards[0]), header.to_string() + a).unwrap();
+        fs::write(crate::stats::path_for(&shards[1]), header.to_string() + b).unwrap();
+        // the third shard has none, and doesn't count
+        assert_eq!(check_stats_headers(&shards), Vec::<String>::new());
+
+        let output = dir.join("merged-stats.csv");
+        merge_stats(&shards, &output).unwrap();
+        let merged = fs::read_to_string(&output).unwrap();
+        assert_eq!(merged, header.to_string() + a + "math,20,8,nodes,+,6\n");
+
+        fs::write(crate::stats::path_for(&shards[2]), "benchmark,stat,value\n").unwrap();
+        assert_eq!(check_stats_headers(&shards).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
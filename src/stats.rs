@@ -0,0 +1,258 @@
+//! Statistics about the e-graphs that the patterns are searched in.
+//!
+//! They go to a companion file of the results, `<results>-stats.csv`, with a
+//! group of rows `benchmark, node_size, class_size, stat, key, value` for every
+//! e-graph snapshot, written in the first of the `--samples` only. The stats
+//! are
+//!
+//! - `nodes`: how many e-nodes have the operator `key`, i.e. the relation sizes.
+//!   Leaves are grouped by their variant, like `Num` or `Symbol`.
+//! - `class_size`: how many e-classes have `key` e-nodes.
+//! - `parents`: how many e-classes are a child of `key` e-nodes.
+//! - `cyclic_classes`: how many e-classes can reach themselves.
+//! - `depth`: how many e-classes have a shallowest term of depth `key`,
+//!   where leaves have depth 0, or `inf` for those with no finite term.
+//! - `analysis_data`: how many e-classes carry analysis data, like a constant,
+//!   for suites whose analysis has such a notion.
+
+use egg::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// `out/benchmark-stats.csv` for `out/benchmark.csv` or `out/benchmark.jsonl`.
+pub fn path_for(results: &Path) -> PathBuf {
+    crate::sidecar(results, "stats.csv")
+}
+
+#[derive(Debug, Serialize)]
+struct StatRecord<'a> {
+    benchmark: &'a str,
+    node_size: usize,
+    class_size: usize,
+    stat: &'static str,
+    key: String,
+    value: usize,
+}
+
+pub fn write_stats<L, A>(
+    wtr: &mut csv::Writer<std::fs::File>,
+    name: &str,
+    egraph: &EGraph<L, A>,
+    has_data: Option<fn(&A::Data) -> bool>,
+) where
+    L: Language + Display,
+    A: Analysis<L>,
+{
+    let ids: Vec<Id> = egraph.classes().map(|c| c.id).collect();
+    let index: HashMap<Id, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let idx = |id: Id| index[&egraph.find(id)];
+
+    let mut nodes: BTreeMap<String, usize> = BTreeMap::new();
+    let mut class_sizes: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut parents = vec![0; ids.len()];
+    let mut succ: Vec<Vec<usize>> = vec![vec![]; ids.len()];
+    for id in &ids {
+        let class = &egraph[*id];
+        *class_sizes.entry(class.nodes.len()).or_default() += 1;
+        for node in &class.nodes {
+            *nodes.entry(operator_name(node)).or_default() += 1;
+            let mut children: Vec<usize> = node.children().iter().map(|c| idx(*c)).collect();
+            children.sort_unstable();
+            children.dedup();
+            for c in &children {
+                parents[*c] += 1;
+            }
+            succ[idx(*id)].extend(children);
+        }
+    }
+    for s in &mut succ {
+        s.sort_unstable();
+        s.dedup();
+    }
+
+    let mut rows: Vec<(&'static str, String, usize)> = vec![];
+    rows.extend(nodes.into_iter().map(|(op, n)| ("nodes", op, n)));
+    rows.extend(
+        class_sizes
+            .into_iter()
+            .map(|(k, n)| ("class_size", k.to_string(), n)),
+    );
+    rows.extend(histogram(&parents).map(|(k, n)| ("parents", k.to_string(), n)));
+    rows.push(("cyclic_classes", String::new(), cyclic_classes(&succ)));
+    rows.extend(histogram(&depths(egraph, &ids, idx)).map(|(k, n)| {
+        let key = k.map_or_else(|| "inf".to_string(), |d| d.to_string());
+        ("depth", key, n)
+    }));
+    if let Some(has_data) = has_data {
+        let n = ids.iter().filter(|id| has_data(&egraph[**id].data)).count();
+        rows.push(("analysis_data", String::new(), n));
+    }
+
+    for (stat, key, value) in rows {
+        wtr.serialize(StatRecord {
+            benchmark: name,
+            node_size: egraph.total_number_of_nodes(),
+            class_size: egraph.number_of_classes(),
+            stat,
+            key,
+            value,
+        })
+        .unwrap();
+    }
+    wtr.flush().unwrap();
+}
+
+/// The operator of an e-node, or for a leaf the name of its variant.
+fn operator_name<L: Language + Display>(node: &L) -> String {
+    if node.is_leaf() {
+        let debug = format!("{:?}", node);
+        debug.split('(').next().unwrap().to_string()
+    } else {
+        node.to_string()
+    }
+}
+
+fn histogram<T: Ord + Copy>(values: &[T]) -> impl Iterator<Item = (T, usize)> {
+    let mut counts: BTreeMap<T, usize> = BTreeMap::new();
+    for v in values {
+        *counts.entry(*v).or_default() += 1;
+    }
+    counts.into_iter()
+}
+
+/// The number of vertices that are in a cycle, found with Tarjan's strongly
+/// connected components algorithm, without recursion since e-graphs get deep.
+fn cyclic_classes(succ: &[Vec<usize>]) -> usize {
+    let n = succ.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut next = 0;
+    let mut cyclic = 0;
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // vertices being visited, with the index of the next successor to visit
+        let mut calls = vec![(root, 0)];
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        while let Some(&(v, i)) = calls.last() {
+            if let Some(&w) = succ[v].get(i) {
+                calls.last_mut().unwrap().1 += 1;
+                if index[w] == usize::MAX {
+                    index[w] = next;
+                    low[w] = next;
+                    next += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            calls.pop();
+            if let Some(&(u, _)) = calls.last() {
+                low[u] = low[u].min(low[v]);
+            }
+            if low[v] == index[v] {
+                let mut size = 0;
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    size += 1;
+                    if w == v {
+                        break;
+                    }
+                }
+                if size > 1 || succ[v].contains(&v) {
+                    cyclic += size;
+                }
+            }
+        }
+    }
+    cyclic
+}
+
+/// The depth of the shallowest term in each class, by iterating to a fixpoint,
+/// or `None` if every term of the class is infinite.
+fn depths<L: Language, A: Analysis<L>>(
+    egraph: &EGraph<L, A>,
+    ids: &[Id],
+    idx: impl Fn(Id) -> usize,
+) -> Vec<Option<usize>> {
+    let mut depth = vec![usize::MAX; ids.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, id) in ids.iter().enumerate() {
+            for node in &egraph[*id].nodes {
+                let children = node.children().iter().map(|c| depth[idx(*c)]);
+                let d = match children.max() {
+                    None => 0,
+                    Some(usize::MAX) => continue,
+                    Some(d) => d + 1,
+                };
+                if d < depth[i] {
+                    depth[i] = d;
+                    changed = true;
+                }
+            }
+        }
+    }
+    depth
+        .into_iter()
+        .map(|d| if d == usize::MAX { None } else { Some(d) })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles() {
+        // a path, a diamond and a tree have no cycles
+        assert_eq!(cyclic_classes(&[vec![1], vec![2], vec![]]), 0);
+        assert_eq!(cyclic_classes(&[vec![1, 2], vec![3], vec![3], vec![]]), 0);
+        // 0 -> 1 -> 2 -> 0 with 3 leading into it, and 4 with a self-loop
+        let succ = [vec![1], vec![2], vec![0], vec![0], vec![4]];
+        assert_eq!(cyclic_classes(&succ), 4);
+        // two cycles joined by an edge are two components
+        let succ = [vec![1], vec![0, 2], vec![3], vec![2]];
+        assert_eq!(cyclic_classes(&succ), 4);
+    }
+
+    #[test]
+    fn shallowest_terms() {
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        let a = egraph.add_expr(&"a".parse().unwrap());
+        let fab = egraph.add_expr(&"(f a b)".parse().unwrap());
+        let g = egraph.add_expr(&"(g (f a b))".parse().unwrap());
+        let h = egraph.add_expr(&"(h (g (f a b)))".parse().unwrap());
+        let depths_of = |egraph: &EGraph<SymbolLang, ()>, id: Id| {
+            let ids: Vec<Id> = egraph.classes().map(|c| c.id).collect();
+            let index: HashMap<Id, usize> =
+                ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+            let idx = |id: Id| index[&egraph.find(id)];
+            depths(egraph, &ids, idx)[idx(id)]
+        };
+        assert_eq!(depths_of(&egraph, a), Some(0));
+        assert_eq!(depths_of(&egraph, g), Some(2));
+        assert_eq!(depths_of(&egraph, h), Some(3));
+
+        // a = (g (f a b)) makes a cycle through a, which keeps its leaf
+        egraph.union(a, g);
+        egraph.rebuild();
+        assert_eq!(depths_of(&egraph, g), Some(0));
+        assert_eq!(depths_of(&egraph, fab), Some(1));
+        assert_eq!(depths_of(&egraph, h), Some(1));
+    }
+}
@@ -0,0 +1,65 @@
+//! How a timed search ended. Only `ok` and `timeout` say anything about how
+//! fast the search is; the other statuses are failures, and their times
+//! should never be read as timings.
+
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt::{self, Display};
+use std::fs;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Still running after `--timeout`, recorded with the timeout as its time.
+    Timeout,
+    /// The search panicked, with the panic message in the `message` column.
+    Panic,
+    /// The process grew by more than `--memory-limit` while the search ran.
+    Oom,
+    /// Not run because an earlier search of the same pattern failed.
+    Skipped,
+}
+
+impl Status {
+    /// Whether the time is a timing of the search, exact or a lower bound.
+    pub fn is_measured(self) -> bool {
+        matches!(self, Status::Ok | Status::Timeout)
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Status::Ok => "ok",
+            Status::Timeout => "timeout",
+            Status::Panic => "panic",
+            Status::Oom => "oom",
+            Status::Skipped => "skipped",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The message a panic was started with, from `catch_unwind`.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(s) => *s,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(s) => s.to_string(),
+            Err(_) => "unknown panic".into(),
+        },
+    }
+}
+
+/// The resident memory of this process in kB, if /proc has it.
+pub fn rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    line.trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
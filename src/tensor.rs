@@ -0,0 +1,100 @@
+use crate::*;
+use egg::{define_language, rewrite as rw, Id, Symbol};
+
+pub fn tensor_bench() -> Result<Bench<Tensor, ()>, Vec<String>> {
+    Ok(Bench {
+        name: "tensor".into(),
+        start_exprs: [
+            // two-layer perceptron
+            "(relu (matmul (relu (matmul x w1)) w2))",
+            // self-attention scores
+            "(matmul (matmul (matmul x wq) (transpose (matmul x wk))) (matmul x wv))",
+            // inception-style block with parallel convolutions
+            "(concat 1 (conv 1 1 0 2 x w1)
+                (concat 1 (relu (conv 1 1 1 0 x w3a))
+                          (relu (conv 1 1 1 0 x w3b))))",
+            // residual block
+            "(relu (ewadd x (conv 1 1 1 0 (relu (conv 1 1 1 0 x w1)) w2)))",
+            // lstm-style gates
+            "(concat 1 (ewadd (matmul x wi) (matmul h ui))
+                       (ewadd (matmul x wf) (matmul h uf)))",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rules: rules(),
+        bench_pats: parse_patterns("tensor")?,
+        conditionals: vec![],
+        has_data: None,
+    })
+}
+
+define_language! {
+    pub enum Tensor {
+        "ewadd" = Ewadd([Id; 2]),
+        "ewmul" = Ewmul([Id; 2]),
+        "relu" = Relu(Id),
+        "transpose" = Transpose(Id),
+        "matmul" = Matmul([Id; 2]),
+        // stride-h, stride-w, padding, activation, input, weight
+        "conv" = Conv([Id; 6]),
+        // axis, left, right
+        "concat" = Concat([Id; 3]),
+        // axis, input; splits at the point of the concat it came from
+        "split" = Split([Id; 2]),
+        "split0" = Split0(Id),
+        "split1" = Split1(Id),
+
+        Num(i32),
+        Symbol(Symbol),
+    }
+}
+
+// activation 0 is none and activation 2 is relu, as in TASO
+#[rustfmt::skip]
+pub fn rules() -> Vec<Rewrite<Tensor, ()>> { vec![
+    rw!("ewadd-comm";  "(ewadd ?x ?y)"            => "(ewadd ?y ?x)"),
+    rw!("ewmul-comm";  "(ewmul ?x ?y)"            => "(ewmul ?y ?x)"),
+    rw!("ewadd-assoc"; "(ewadd ?x (ewadd ?y ?z))" => "(ewadd (ewadd ?x ?y) ?z)"),
+    rw!("ewmul-assoc"; "(ewmul ?x (ewmul ?y ?z))" => "(ewmul (ewmul ?x ?y) ?z)"),
+    rw!("ewmul-dist";   "(ewmul (ewadd ?x ?y) ?z)"          => "(ewadd (ewmul ?x ?z) (ewmul ?y ?z))"),
+    rw!("ewmul-factor"; "(ewadd (ewmul ?x ?z) (ewmul ?y ?z))" => "(ewmul (ewadd ?x ?y) ?z)"),
+
+    rw!("matmul-assoc";  "(matmul ?x (matmul ?y ?z))"           => "(matmul (matmul ?x ?y) ?z)"),
+    rw!("matmul-dist";   "(matmul ?x (ewadd ?y ?z))"            => "(ewadd (matmul ?x ?y) (matmul ?x ?z))"),
+    rw!("matmul-factor"; "(ewadd (matmul ?x ?y) (matmul ?x ?z))" => "(matmul ?x (ewadd ?y ?z))"),
+
+    rw!("transpose-inv";    "(transpose (transpose ?x))"  => "?x"),
+    rw!("transpose-matmul"; "(transpose (matmul ?x ?y))"  => "(matmul (transpose ?y) (transpose ?x))"),
+    rw!("matmul-transpose"; "(matmul (transpose ?y) (transpose ?x))" => "(transpose (matmul ?x ?y))"),
+    rw!("transpose-ewadd";  "(transpose (ewadd ?x ?y))"   => "(ewadd (transpose ?x) (transpose ?y))"),
+    rw!("transpose-relu";   "(transpose (relu ?x))"       => "(relu (transpose ?x))"),
+
+    rw!("conv-fuse-relu"; "(relu (conv ?sh ?sw ?p 0 ?x ?w))" => "(conv ?sh ?sw ?p 2 ?x ?w)"),
+    rw!("conv-weight-add";
+        "(ewadd (conv ?sh ?sw ?p 0 ?x ?w1) (conv ?sh ?sw ?p 0 ?x ?w2))" =>
+        "(conv ?sh ?sw ?p 0 ?x (ewadd ?w1 ?w2))"),
+    rw!("conv-input-add";
+        "(conv ?sh ?sw ?p 0 (ewadd ?x ?y) ?w)" =>
+        "(ewadd (conv ?sh ?sw ?p 0 ?x ?w) (conv ?sh ?sw ?p 0 ?y ?w))"),
+
+    rw!("concat-matmul";
+        "(concat 1 (matmul ?x ?w1) (matmul ?x ?w2))" =>
+        "(matmul ?x (concat 1 ?w1 ?w2))"),
+    rw!("concat-conv";
+        "(concat 1 (conv ?sh ?sw ?p ?a ?x ?w1) (conv ?sh ?sw ?p ?a ?x ?w2))" =>
+        "(conv ?sh ?sw ?p ?a ?x (concat 0 ?w1 ?w2))"),
+    rw!("concat-ewadd";
+        "(ewadd (concat ?ax ?x1 ?x2) (concat ?ax ?y1 ?y2))" =>
+        "(concat ?ax (ewadd ?x1 ?y1) (ewadd ?x2 ?y2))"),
+    rw!("concat-relu"; "(relu (concat ?ax ?x ?y))" => "(concat ?ax (relu ?x) (relu ?y))"),
+
+    rw!("split-concat-0"; "(split0 (split ?ax (concat ?ax ?x ?y)))" => "?x"),
+    rw!("split-concat-1"; "(split1 (split ?ax (concat ?ax ?x ?y)))" => "?y"),
+    rw!("concat-split";
+        "(concat ?ax (split0 (split ?ax ?x)) (split1 (split ?ax ?x)))" => "?x"),
+    rw!("split-matmul";
+        "(matmul ?x (concat 1 ?w1 ?w2))" =>
+        "(concat 1 (split0 (split 1 (matmul ?x (concat 1 ?w1 ?w2))))
+                   (split1 (split 1 (matmul ?x (concat 1 ?w1 ?w2)))))"),
+]}
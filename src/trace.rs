@@ -0,0 +1,96 @@
+//! A timeline of the run in the Chrome trace event format, written with
+//! `--trace`, to see where a long run spends its time. Open it in
+//! `chrome://tracing` or https://ui.perfetto.dev.
+//!
+//! Every phase is a complete (`"ph": "X"`) event on one thread, so nested
+//! phases show up as a stack: a bench contains the saturation of each
+//! e-graph and the searches on it, and the saturation contains the runner's
+//! iterations.
+
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+/// How often the events written so far are flushed to the file.
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+pub struct Trace {
+    file: Option<BufWriter<File>>,
+    start: Instant,
+    events: usize,
+    flushed: Instant,
+}
+
+impl Trace {
+    /// A trace that records nothing.
+    pub fn disabled() -> Self {
+        Trace {
+            file: None,
+            start: Instant::now(),
+            events: 0,
+            flushed: Instant::now(),
+        }
+    }
+
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "[")?;
+        Ok(Trace {
+            file: Some(file),
+            start: Instant::now(),
+            events: 0,
+            flushed: Instant::now(),
+        })
+    }
+
+    /// Records a phase that started at `start` and ends now.
+    pub fn span(&mut self, name: &str, cat: &str, start: Instant, args: Value) {
+        self.event(name, cat, start, start.elapsed(), args)
+    }
+
+    /// Records a phase that started at `start` and took `duration`.
+    pub fn event(
+        &mut self,
+        name: &str,
+        cat: &str,
+        start: Instant,
+        duration: Duration,
+        args: Value,
+    ) {
+        let ts = start.saturating_duration_since(self.start);
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let event = json!({
+            "name": name,
+            "cat": cat,
+            "ph": "X",
+            "ts": ts.as_secs_f64() * 1e6,
+            "dur": duration.as_secs_f64() * 1e6,
+            "pid": 1,
+            "tid": 1,
+            "args": args,
+        });
+        let sep = if self.events == 0 { "" } else { ",\n" };
+        write!(file, "{}{}", sep, event).unwrap();
+        self.events += 1;
+        // flushed now and then rather than after every event, which would
+        // slow down the phases it times, so the trace of a run that was
+        // killed can still be opened up to its last second or so; the viewers
+        // accept a missing `]`
+        if self.flushed.elapsed() >= FLUSH_EVERY {
+            file.flush().unwrap();
+            self.flushed = Instant::now();
+        }
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        if let Some(file) = &mut self.file {
+            let _ = writeln!(file, "\n]");
+        }
+    }
+}